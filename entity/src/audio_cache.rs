@@ -0,0 +1,20 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "audio_cache")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub video_id: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub itag: u32, // YouTube format of the audio
+    pub file_name: String, // Name of the file in the cache directory
+    pub size: i64,         // In bytes
+    /// Loudness given by YouTube, relative to its reference loudness
+    pub loudness_db: Option<f64>,
+    pub played_at: i64, // Unix timestamp in seconds of the last time the file was played
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,32 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "history")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub video_id: String,
+    pub added_by: String,
+    pub auto_added: bool,
+    pub started_at: i64, // Unix timestamp in seconds
+    pub ended_at: i64,   // Unix timestamp in seconds
+    pub outcome: String, // finished, skipped or failed
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::video::Entity",
+        from = "Column::VideoId",
+        to = "super::video::Column::Id"
+    )]
+    Video,
+}
+
+impl Related<super::video::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Video.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,3 +1,13 @@
+#[cfg(feature = "audio_cache")]
+pub mod audio_cache;
+#[cfg(feature = "history")]
+pub mod history;
+#[cfg(feature = "queue")]
+pub mod queue;
+#[cfg(feature = "setting")]
+pub mod setting;
+#[cfg(feature = "track")]
+pub mod track;
 #[cfg(feature = "user")]
 pub mod user;
 #[cfg(feature = "video")]
@@ -0,0 +1,32 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "queue")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub position: i32, // Position of the entry in the queue (0 is the music playing)
+    pub video_id: String,
+    pub added_by: String,
+    pub added_at: i64,     // Unix timestamp in seconds
+    pub auto_added: bool,  // Queued by the autoplay when the queue ran dry
+    pub rate: Option<f64>, // Playback rate of the entry, the global one when None
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::video::Entity",
+        from = "Column::VideoId",
+        to = "super::video::Column::Id"
+    )]
+    Video,
+}
+
+impl Related<super::video::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Video.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "tracks")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub uri: String, // file:// uri of the track, used as the id of its video
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration_ms: i64,
+    pub scanned_at: i64, // Unix timestamp in seconds of the last scan that found the track
+    pub loudness_lufs: Option<f64>, // From the ReplayGain tags of the file or measured by the scan
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,90 @@
+use crate::login::jwt_token::AuthToken;
+use crate::music_player::sink::OutputSink;
+use crate::music_player::MusicPlayerMessage;
+use crate::{sql, AppState};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use sea_orm::DbErr;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tracing::log;
+
+/// Why a request of the admin API failed
+#[derive(Debug)]
+pub enum AdminError {
+    Forbidden,
+    InvalidRequest(String),
+    Internal(String),
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = match self {
+            AdminError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "Reserved to the administrators".to_owned(),
+            ),
+            AdminError::InvalidRequest(message) => (StatusCode::BAD_REQUEST, message),
+            AdminError::Internal(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+        };
+        let body = Json(json!({
+            "error": error_message,
+        }));
+        (status, body).into_response()
+    }
+}
+
+impl From<DbErr> for AdminError {
+    fn from(err: DbErr) -> Self {
+        AdminError::Internal(format!("Database error: {err}"))
+    }
+}
+
+fn check_admin(state: &AppState, token: &AuthToken) -> Result<(), AdminError> {
+    if state.config.is_admin(&token.username) {
+        Ok(())
+    } else {
+        log::warn!("{} is not an administrator", token.username);
+        Err(AdminError::Forbidden)
+    }
+}
+
+#[axum::debug_handler]
+pub async fn get_output(
+    State(state): State<Arc<AppState>>,
+    token: AuthToken,
+) -> Result<Json<OutputSink>, AdminError> {
+    log::info!("Get /api/admin/output");
+    check_admin(&state, &token)?;
+    Ok(Json(state.output.lock().await.clone()))
+}
+
+/// Switch the sink the music player plays on, it is kept after a restart
+#[axum::debug_handler]
+pub async fn set_output(
+    State(state): State<Arc<AppState>>,
+    token: AuthToken,
+    Json(sink): Json<OutputSink>,
+) -> Result<Json<OutputSink>, AdminError> {
+    log::info!("Put /api/admin/output: {sink:?}");
+    check_admin(&state, &token)?;
+    let (reply_tx, reply_rx) = oneshot::channel();
+    state
+        .music_player_tx
+        .send(MusicPlayerMessage::SetOutput(sink.clone(), reply_tx))
+        .map_err(|_| AdminError::Internal("The music player is not running".to_owned()))?;
+    reply_rx
+        .await
+        .map_err(|_| AdminError::Internal("The music player is not running".to_owned()))?
+        .map_err(AdminError::InvalidRequest)?;
+    // The music player locks the output too, it is not held while waiting for its reply
+    *state.output.lock().await = sink.clone();
+
+    let saved =
+        serde_json::to_string(&sink).map_err(|err| AdminError::Internal(err.to_string()))?;
+    sql::setting::set(&state.conn, sql::setting::OUTPUT, saved).await?;
+    Ok(Json(sink))
+}
@@ -0,0 +1,195 @@
+use crate::music_player::{random_below, MusicPlayerMessage};
+use crate::websocket::youtube_video;
+use crate::{sql, AppState};
+use anyhow::{anyhow, bail, Error, Result};
+use entity::video::Model as Video;
+use jukebox_rust::NetData;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::log;
+
+/// Shown as the user who queued the musics of the autoplay
+pub const AUTOPLAY_USER: &str = "autoplay";
+/// Number of musics picked by the autoplay that are not picked again, so that it does not loop between a few videos
+const RECENT_CAPACITY: usize = 50;
+
+/// Where the autoplay picks the music queued when the queue runs dry
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum AutoplayStrategy {
+    /// The room goes silent at the end of the queue
+    Off,
+    /// Videos YouTube recommends after the last music, the history is used after the other musics
+    #[default]
+    Related,
+    /// Random videos of a YouTube playlist, the ones picked recently are only picked again at the end of the playlist
+    Playlist { id: String },
+    /// Random musics played until their end before
+    History,
+}
+
+/// Parse the strategy given in the configuration: `off`, `related`, `playlist:<id>` or `history`
+impl FromStr for AutoplayStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            None if s == "off" => AutoplayStrategy::Off,
+            None if s == "related" => AutoplayStrategy::Related,
+            None if s == "history" => AutoplayStrategy::History,
+            Some(("playlist", id)) if !id.is_empty() => {
+                AutoplayStrategy::Playlist { id: id.to_owned() }
+            }
+            _ => bail!("Unknown autoplay strategy: {s}"),
+        })
+    }
+}
+
+/// State of the autoplay shared by the music player
+#[derive(Default)]
+pub struct Autoplay {
+    running: AtomicBool,             // A music is being picked
+    recent: Mutex<VecDeque<String>>, // Ids of the last musics picked
+}
+
+/// Queue a music picked by the strategy of the configuration, `last` is the last music of the queue
+pub fn run(state: &Arc<AppState>, last: Video) {
+    if state.config.autoplay == AutoplayStrategy::Off
+        || state.autoplay.running.swap(true, Ordering::SeqCst)
+    {
+        return;
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(err) = queue_music(&state, last).await {
+            log::error!("Autoplay failed: {err}");
+        }
+        state.autoplay.running.store(false, Ordering::SeqCst);
+    });
+}
+
+async fn queue_music(state: &AppState, last: Video) -> Result<()> {
+    let mut recent = state.autoplay.recent.lock().await;
+    // The musics of the queue are not queued twice
+    let queued: Vec<String> = state
+        .list
+        .lock()
+        .await
+        .iter()
+        .map(|entry| entry.video.id.clone())
+        .collect();
+    let excluded: Vec<String> = queued.iter().chain(recent.iter()).cloned().collect();
+
+    let video = match &state.config.autoplay {
+        AutoplayStrategy::Off => None,
+        AutoplayStrategy::Related => match related_video(&last, &excluded).await {
+            Some(video) => Some(video),
+            None => history_video(state, &queued, &excluded).await?,
+        },
+        AutoplayStrategy::Playlist { id } => {
+            let videos: Vec<Video> = my_youtube_extractor::playlist_videos(id)
+                .await
+                .map_err(|err| anyhow!("Error getting the playlist {id}: {err}"))?
+                .iter()
+                .map(youtube_video)
+                .collect();
+            random_video(&videos, &excluded).or_else(|| random_video(&videos, &queued))
+        }
+        AutoplayStrategy::History => history_video(state, &queued, &excluded).await?,
+    };
+    let Some(video) = video else {
+        log::info!("Nothing to autoplay");
+        return Ok(());
+    };
+    log::info!("Autoplaying {}", video.title);
+    if recent.len() == RECENT_CAPACITY {
+        recent.pop_front();
+    }
+    recent.push_back(video.id.clone());
+    drop(recent);
+
+    let mut playlist = state.list.lock().await;
+    let entry = sql::queue::push(&state.conn, playlist.len(), &video, AUTOPLAY_USER, true).await?;
+    playlist.push(entry.clone());
+    state
+        .music_player_tx
+        .send(MusicPlayerMessage::AddMusic(entry.clone()))
+        .map_err(|_| anyhow!("The music player is not running"))?;
+    let _ = state.tx.send(NetData::Added(entry));
+    Ok(())
+}
+
+/// First video YouTube recommends after `last` that is not `excluded`
+async fn related_video(last: &Video, excluded: &[String]) -> Option<Video> {
+    if !is_youtube(last) {
+        return None;
+    }
+    match my_youtube_extractor::related_videos(&last.id).await {
+        Ok(videos) => videos
+            .iter()
+            .map(youtube_video)
+            .find(|video| !excluded.contains(&video.id)),
+        Err(err) => {
+            log::error!("Error getting the videos related to {}: {err}", last.id);
+            None
+        }
+    }
+}
+
+/// Random music of the history, the ones picked recently are only picked again when there is no other one
+async fn history_video(
+    state: &AppState,
+    queued: &[String],
+    excluded: &[String],
+) -> Result<Option<Video>> {
+    match sql::history::random_video(&state.conn, excluded).await? {
+        Some(video) => Ok(Some(video)),
+        None => Ok(sql::history::random_video(&state.conn, queued).await?),
+    }
+}
+
+fn random_video(videos: &[Video], excluded: &[String]) -> Option<Video> {
+    let candidates: Vec<&Video> = videos
+        .iter()
+        .filter(|video| !excluded.contains(&video.id))
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    Some(candidates[random_below(candidates.len())].clone())
+}
+
+/// The other sources have an uri scheme
+fn is_youtube(video: &Video) -> bool {
+    !video.id.contains(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_strategy() {
+        let cases = [
+            ("off", Some(AutoplayStrategy::Off)),
+            ("related", Some(AutoplayStrategy::Related)),
+            ("history", Some(AutoplayStrategy::History)),
+            (
+                "playlist:PL123",
+                Some(AutoplayStrategy::Playlist {
+                    id: "PL123".to_owned(),
+                }),
+            ),
+            ("playlist:", None),
+            ("playlist", None),
+            ("related:PL123", None),
+            ("random", None),
+            ("", None),
+        ];
+        for (strategy, expected) in cases {
+            assert_eq!(strategy.parse().ok(), expected, "{strategy:?}");
+        }
+    }
+}
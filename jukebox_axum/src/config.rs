@@ -0,0 +1,90 @@
+use crate::autoplay::AutoplayStrategy;
+use crate::music_player::sink::OutputSink;
+use std::path::PathBuf;
+use tracing::log;
+
+/// Directories of the local music library, separated like the `PATH` variable
+const LIBRARY_DIRS_ENV: &str = "JUKEBOX_LIBRARY_DIRS";
+/// Sink used until an administrator picks another one, see [`OutputSink`] for its format
+const OUTPUT_ENV: &str = "JUKEBOX_OUTPUT";
+/// Logins of the users allowed to use the admin API, separated by commas
+const ADMINS_ENV: &str = "JUKEBOX_ADMINS";
+/// What is played when the queue runs dry, see [`AutoplayStrategy`] for its format
+const AUTOPLAY_ENV: &str = "JUKEBOX_AUTOPLAY";
+/// Directory the audios of YouTube are downloaded to, and the test tones written to
+const CACHE_DIR_ENV: &str = "JUKEBOX_CACHE_DIR";
+/// Size in megabytes above which the least recently played audios are removed from the cache, 0 disables it
+const CACHE_SIZE_ENV: &str = "JUKEBOX_CACHE_SIZE_MB";
+/// Hosts the clients may queue plain HTTP streams from, separated by commas, none by default
+const HTTP_HOSTS_ENV: &str = "JUKEBOX_HTTP_HOSTS";
+/// Set to `true` to let the clients queue the sine waves of `tone:<frequency>[:<seconds>]` ids
+const TEST_TONES_ENV: &str = "JUKEBOX_TEST_TONES";
+const DEFAULT_CACHE_DIR: &str = "audio_cache";
+const DEFAULT_CACHE_SIZE_MB: u64 = 2048;
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// Settings of the server read from the environment at startup
+pub struct Config {
+    pub library_dirs: Vec<PathBuf>,
+    pub output: OutputSink,
+    pub admins: Vec<String>,
+    pub autoplay: AutoplayStrategy,
+    pub cache_dir: PathBuf,
+    pub cache_size: u64, // In bytes
+    pub http_hosts: Vec<String>,
+    pub test_tones: bool,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            library_dirs: std::env::var_os(LIBRARY_DIRS_ENV)
+                .map(|dirs| std::env::split_paths(&dirs).collect())
+                .unwrap_or_default(),
+            output: match std::env::var(OUTPUT_ENV) {
+                Ok(output) => output.parse().unwrap_or_else(|err| {
+                    log::error!("Invalid {OUTPUT_ENV}: {err}");
+                    OutputSink::default()
+                }),
+                Err(_) => OutputSink::default(),
+            },
+            admins: comma_separated(ADMINS_ENV),
+            autoplay: match std::env::var(AUTOPLAY_ENV) {
+                Ok(autoplay) => autoplay.parse().unwrap_or_else(|err| {
+                    log::error!("Invalid {AUTOPLAY_ENV}: {err}");
+                    AutoplayStrategy::default()
+                }),
+                Err(_) => AutoplayStrategy::default(),
+            },
+            cache_dir: std::env::var_os(CACHE_DIR_ENV)
+                .map_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR), PathBuf::from),
+            cache_size: BYTES_PER_MB
+                * match std::env::var(CACHE_SIZE_ENV) {
+                    Ok(size) => size.trim().parse().unwrap_or_else(|err| {
+                        log::error!("Invalid {CACHE_SIZE_ENV}: {err}");
+                        DEFAULT_CACHE_SIZE_MB
+                    }),
+                    Err(_) => DEFAULT_CACHE_SIZE_MB,
+                },
+            http_hosts: comma_separated(HTTP_HOSTS_ENV),
+            test_tones: std::env::var(TEST_TONES_ENV).is_ok_and(|tones| tones.trim() == "true"),
+        }
+    }
+
+    pub fn is_admin(&self, username: &str) -> bool {
+        self.admins.iter().any(|admin| admin == username)
+    }
+}
+
+/// Non-empty values of a variable separated by commas
+fn comma_separated(var: &str) -> Vec<String> {
+    std::env::var(var)
+        .map(|values| {
+            values
+                .split(',')
+                .map(|value| value.trim().to_owned())
+                .filter(|value| !value.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
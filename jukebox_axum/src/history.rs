@@ -0,0 +1,53 @@
+use crate::login::jwt_token::AuthToken;
+use crate::{sql, AppState};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use jukebox_rust::{HistoryPage, PlayOutcome};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::log;
+
+const DEFAULT_PER_PAGE: u64 = 50;
+const MAX_PER_PAGE: u64 = 200;
+
+/// Parameters of `/api/history`, the filters are optional
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    page: u64, // Starts at 0
+    per_page: Option<u64>,
+    user: Option<String>,   // User who queued the music
+    search: Option<String>, // Part of the title
+    outcome: Option<PlayOutcome>,
+}
+
+/// Musics played by the jukebox, the most recent first
+#[axum::debug_handler]
+pub async fn history_handler(
+    State(state): State<Arc<AppState>>,
+    _token: AuthToken,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryPage>, (StatusCode, Json<Value>)> {
+    log::info!("Get /api/history: {query:?}");
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let filter = sql::history::Filter {
+        user: query.user.filter(|user| !user.is_empty()),
+        search: query.search.filter(|search| !search.is_empty()),
+        outcome: query.outcome,
+    };
+    let (entries, total) = sql::history::page(&state.conn, &filter, query.page, per_page)
+        .await
+        .map_err(|err| {
+            log::error!("Error loading the history: {err}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Cannot load the history" })),
+            )
+        })?;
+    Ok(Json(HistoryPage { entries, total }))
+}
@@ -0,0 +1,250 @@
+use crate::music_player::resolver::REPLAYGAIN_REFERENCE_LUFS;
+use crate::music_player::sink::quote;
+use crate::sql;
+use anyhow::{anyhow, bail, Result};
+use entity::track;
+use entity::video::Model as Video;
+use gstreamer::prelude::ElementExt;
+use gstreamer::tags::{self, Tag};
+use gstreamer::{glib, ClockTime, MessageView, State, TagList};
+use gstreamer_pbutils::Discoverer;
+use sea_orm::DatabaseConnection;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tracing::log;
+
+const AUDIO_EXTENSIONS: &[&str] = &["flac", "mp3", "ogg", "opus", "m4a", "wav"];
+const DISCOVER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time given to decode a whole file to measure its loudness, a file taking longer has no loudness
+const MEASURE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Scan the directories of the library and save each track found in the database,
+/// returns the number of tracks found
+pub async fn scan(conn: &DatabaseConnection, dirs: Vec<PathBuf>) -> Result<usize> {
+    let scanned_at = std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let measured = sql::track::loudness(conn).await?;
+
+    // The discoverer and the file system calls are blocking
+    let (tx, mut rx) = mpsc::channel(100);
+    let discovery = tokio::task::spawn_blocking(move || -> Result<()> {
+        gstreamer::init()?;
+        let discoverer =
+            Discoverer::new(ClockTime::from_mseconds(DISCOVER_TIMEOUT.as_millis() as u64))?;
+        let mut files = vec![];
+        for dir in dirs.iter() {
+            find_audio_files(dir, &mut files);
+        }
+        let tracks = files
+            .iter()
+            .filter_map(|path| discover(&discoverer, path, scanned_at, &measured));
+        for track in tracks {
+            if tx.blocking_send(track).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    let mut found = 0;
+    while let Some(track) = rx.recv().await {
+        sql::track::save(conn, track).await?;
+        found += 1;
+    }
+    discovery.await??;
+    let removed = sql::track::remove_missing(conn, scanned_at).await?;
+    log::info!("Library scanned: {found} tracks found, {removed} removed");
+    Ok(found)
+}
+
+/// Measure the loudness of the tracks that have no ReplayGain tags, after a scan,
+/// returns the number of tracks measured
+pub async fn measure_tracks(conn: &DatabaseConnection) -> Result<usize> {
+    let uris = sql::track::unmeasured(conn).await?;
+
+    // Decoding the files is blocking
+    let (tx, mut rx) = mpsc::channel(100);
+    let measuring = tokio::task::spawn_blocking(move || -> Result<()> {
+        gstreamer::init()?;
+        for uri in uris {
+            match measure_loudness(&uri) {
+                Ok(loudness) => {
+                    if tx.blocking_send((uri, loudness)).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => log::error!("Cannot measure {uri}: {err}"),
+            }
+        }
+        Ok(())
+    });
+
+    let mut measured = 0;
+    while let Some((uri, loudness)) = rx.recv().await {
+        sql::track::set_loudness(conn, &uri, loudness).await?;
+        measured += 1;
+    }
+    measuring.await??;
+    log::info!("Library measured: {measured} tracks");
+    Ok(measured)
+}
+
+/// Video sent to the clients for a track, its id is the uri of the track
+pub fn track_video(track: track::Model) -> Video {
+    let seconds = track.duration_ms / 1000;
+    Video {
+        id: track.uri,
+        title: track.title,
+        thumbnail: String::new(),
+        author: track.artist,
+        duration: format!("{}:{:02}", seconds / 60, seconds % 60),
+    }
+}
+
+fn find_audio_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::error!("Cannot read {}: {err}", dir.display());
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // The links to directories are not followed, as they could loop
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            find_audio_files(&path, files);
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Read the tags, the duration and the loudness of an audio file,
+/// the loudness comes from the ReplayGain tags or from a previous measure in `measured`
+fn discover(
+    discoverer: &Discoverer,
+    path: &Path,
+    scanned_at: i64,
+    measured: &HashMap<String, f64>,
+) -> Option<track::Model> {
+    let uri = glib::filename_to_uri(path, None)
+        .map_err(|err| log::error!("Invalid path {}: {err}", path.display()))
+        .ok()?
+        .to_string();
+    let info = discoverer
+        .discover_uri(&uri)
+        .map_err(|err| log::error!("Cannot read {}: {err}", path.display()))
+        .ok()?;
+
+    let tag_list = info.tags();
+    let title = text_tag::<tags::Title>(tag_list.as_ref())
+        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    let artist =
+        text_tag::<tags::Artist>(tag_list.as_ref()).unwrap_or_else(|| "Unknown artist".to_owned());
+    let album = text_tag::<tags::Album>(tag_list.as_ref()).unwrap_or_default();
+    let loudness_lufs = tag_list
+        .as_ref()
+        .and_then(|t| t.get::<tags::TrackGain>())
+        .map(|gain| REPLAYGAIN_REFERENCE_LUFS - gain.get())
+        .or_else(|| measured.get(&uri).copied());
+
+    Some(track::Model {
+        uri,
+        title,
+        artist,
+        album,
+        duration_ms: info.duration().map_or(0, |d| d.mseconds() as i64),
+        scanned_at,
+        loudness_lufs,
+    })
+}
+
+/// Loudness of a file in LUFS, measured by decoding it whole with `rganalysis`
+fn measure_loudness(uri: &str) -> Result<f64> {
+    let pipeline = gstreamer::parse_launch(&format!(
+        "uridecodebin uri={} ! audioconvert ! audioresample ! rganalysis ! fakesink sync=false",
+        quote(uri)
+    ))?;
+    let bus = pipeline
+        .bus()
+        .ok_or_else(|| anyhow!("The pipeline has no bus"))?;
+    pipeline.set_state(State::Playing)?;
+    let deadline = Instant::now() + MEASURE_TIMEOUT;
+    let mut gain = None;
+    let mut result = Err(anyhow!("Timed out"));
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let Some(msg) = bus.timed_pop(ClockTime::from_mseconds(remaining.as_millis() as u64))
+        else {
+            break;
+        };
+        match msg.view() {
+            // The gain computed by `rganalysis` comes with the end of the stream, after the tags of the file
+            MessageView::Tag(tag) => {
+                if let Some(track_gain) = tag.tags().get::<tags::TrackGain>() {
+                    gain = Some(track_gain.get());
+                }
+            }
+            MessageView::Eos(..) => {
+                result = Ok(());
+                break;
+            }
+            MessageView::Error(err) => {
+                result = Err(anyhow!("{}", err.error()));
+                break;
+            }
+            _ => (),
+        }
+    }
+    pipeline.set_state(State::Null)?;
+    result?;
+    match gain {
+        Some(gain) => Ok(REPLAYGAIN_REFERENCE_LUFS - gain),
+        None => bail!("No gain computed"),
+    }
+}
+
+/// Text tag of a file, if it is not empty
+fn text_tag<T>(tags: Option<&TagList>) -> Option<String>
+where
+    T: for<'a> Tag<'a, TagType = &'a str>,
+{
+    tags.and_then(|t| t.get::<T>())
+        .map(|v| v.get().to_owned())
+        .filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_audio_files_skips_linked_dirs() {
+        let dir = std::env::temp_dir().join(format!("jukebox_library_{}", std::process::id()));
+        let sub = dir.join("album");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(dir.join("a.flac"), b"").unwrap();
+        std::fs::write(sub.join("b.MP3"), b"").unwrap();
+        std::fs::write(sub.join("cover.jpg"), b"").unwrap();
+        std::os::unix::fs::symlink(&dir, sub.join("loop")).unwrap();
+
+        let mut files = vec![];
+        find_audio_files(&dir, &mut files);
+        files.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, [dir.join("a.flac"), sub.join("b.MP3")]);
+    }
+}
@@ -1,30 +1,45 @@
 #![feature(is_some_and)]
 #![feature(let_chains)]
 
+mod admin;
+mod autoplay;
+mod config;
+mod history;
+mod library;
 mod login;
 mod music_player;
 mod sql;
+mod stream;
 mod templates;
 mod websocket;
 
 use crate::login::jwt_token::AuthToken;
 use crate::login::{authorize, login_page, register_page, register_post};
+use autoplay::Autoplay;
 use axum::body::{boxed, Body};
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::http::{Request, Response};
 use axum::response::{IntoResponse, Redirect};
 use axum::{routing::get, Json, Router, Server};
-use entity::user;
-use entity::video;
+use config::Config;
+use entity::{queue, setting, track, user, video};
+use jukebox_rust::{
+    Equalizer, EqualizerPreset, Normalization, PlayMode, QueueEntry, Repeat, Transitions,
+    EQUALIZER_BANDS, MAX_PLAYBACK_RATE, MIN_PLAYBACK_RATE,
+};
+use music_player::cache::AudioCache;
+use music_player::resolver::Resolvers;
+use music_player::sink::OutputSink;
 use music_player::MusicPlayerMessage;
-use sea_orm::sea_query::TableCreateStatement;
+use sea_orm::sea_query::{ColumnDef, Table, TableAlterStatement, TableCreateStatement};
 use sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbBackend, DbConn, Schema};
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use stream::AudioStream;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{broadcast, Mutex};
 use tower::ServiceExt;
@@ -32,10 +47,23 @@ use tower_http::services::ServeDir;
 use tracing::log;
 
 pub struct AppState {
-    pub list: Mutex<Vec<video::Model>>,
+    /// Queue of the jukebox, locked before the settings below when both are held at once
+    pub list: Mutex<Vec<QueueEntry>>,
     pub tx: broadcast::Sender<jukebox_rust::NetData>,
     pub conn: DatabaseConnection,
     pub music_player_tx: UnboundedSender<MusicPlayerMessage>,
+    pub volume: Mutex<f64>, // Volume of the music player, between 0 and 100
+    pub transitions: Mutex<Transitions>,
+    pub normalization: Mutex<Normalization>,
+    pub play_mode: Mutex<PlayMode>,
+    pub equalizer: Mutex<Equalizer>,
+    pub rate: Mutex<f64>, // Playback rate of the entries without their own one
+    pub output: Mutex<OutputSink>, // Sink the music player plays on
+    pub stream: Arc<AudioStream>,
+    pub autoplay: Autoplay,
+    pub cache: AudioCache,
+    pub resolvers: Resolvers,
+    pub config: Config,
 }
 
 async fn setup_schema(db: &DbConn) {
@@ -47,6 +75,45 @@ async fn setup_schema(db: &DbConn) {
 
     let stmt: TableCreateStatement = schema.create_table_from_entity(video::Entity);
     let _ = db.execute(db.get_database_backend().build(&stmt)).await;
+
+    let stmt: TableCreateStatement = schema.create_table_from_entity(queue::Entity);
+    let _ = db.execute(db.get_database_backend().build(&stmt)).await;
+
+    let stmt: TableCreateStatement = schema.create_table_from_entity(setting::Entity);
+    let _ = db.execute(db.get_database_backend().build(&stmt)).await;
+
+    let stmt: TableCreateStatement = schema.create_table_from_entity(track::Entity);
+    let _ = db.execute(db.get_database_backend().build(&stmt)).await;
+
+    let stmt: TableCreateStatement = schema.create_table_from_entity(entity::history::Entity);
+    let _ = db.execute(db.get_database_backend().build(&stmt)).await;
+
+    let stmt: TableCreateStatement = schema.create_table_from_entity(entity::audio_cache::Entity);
+    let _ = db.execute(db.get_database_backend().build(&stmt)).await;
+
+    // Columns added after the creation of the tables, fails when they already exist
+    let stmt: TableAlterStatement = Table::alter()
+        .table(queue::Entity)
+        .add_column(
+            ColumnDef::new(queue::Column::AutoAdded)
+                .boolean()
+                .not_null()
+                .default(false),
+        )
+        .to_owned();
+    let _ = db.execute(db.get_database_backend().build(&stmt)).await;
+
+    let stmt: TableAlterStatement = Table::alter()
+        .table(queue::Entity)
+        .add_column(ColumnDef::new(queue::Column::Rate).double().null())
+        .to_owned();
+    let _ = db.execute(db.get_database_backend().build(&stmt)).await;
+
+    let stmt: TableAlterStatement = Table::alter()
+        .table(track::Entity)
+        .add_column(ColumnDef::new(track::Column::LoudnessLufs).double().null())
+        .to_owned();
+    let _ = db.execute(db.get_database_backend().build(&stmt)).await;
 }
 
 #[tokio::main]
@@ -65,16 +132,113 @@ async fn main() {
         .expect("Database connection failed");
     setup_schema(&conn).await;
 
+    // Restore the queue saved before the last shutdown
+    let queue = sql::queue::load_queue(&conn).await.unwrap_or_else(|err| {
+        log::error!("Error loading the queue: {err}");
+        vec![]
+    });
+
+    // Restore the volume set before the last shutdown
+    let volume = match sql::setting::get(&conn, sql::setting::VOLUME).await {
+        Ok(volume) => volume
+            .and_then(|v| v.parse::<f64>().ok())
+            .map_or(100.0, |v| v.clamp(0.0, 100.0)),
+        Err(err) => {
+            log::error!("Error loading the volume: {err}");
+            100.0
+        }
+    };
+
+    let transitions = load_transitions(&conn).await;
+    let normalization = load_normalization(&conn).await;
+    let play_mode = load_play_mode(&conn).await;
+    let equalizer = load_equalizer(&conn).await;
+
+    // Restore the playback rate set before the last shutdown
+    let rate = match sql::setting::get(&conn, sql::setting::PLAYBACK_RATE).await {
+        Ok(rate) => rate
+            .and_then(|r| r.parse::<f64>().ok())
+            .filter(|r| r.is_finite())
+            .map_or(1.0, |r| r.clamp(MIN_PLAYBACK_RATE, MAX_PLAYBACK_RATE)),
+        Err(err) => {
+            log::error!("Error loading the playback rate: {err}");
+            1.0
+        }
+    };
+
+    let config = Config::from_env();
+    // The sink chosen by an administrator replaces the one of the configuration
+    let output = match sql::setting::get(&conn, sql::setting::OUTPUT).await {
+        Ok(Some(output)) => serde_json::from_str(&output).unwrap_or_else(|err| {
+            log::error!("Invalid saved output: {err}");
+            config.output.clone()
+        }),
+        Ok(None) => config.output.clone(),
+        Err(err) => {
+            log::error!("Error loading the output: {err}");
+            config.output.clone()
+        }
+    };
+
     let (music_player_tx, rx1) = tokio::sync::mpsc::unbounded_channel();
+    music_player_tx
+        .send(MusicPlayerMessage::SetVolume(volume))
+        .unwrap();
+    music_player_tx
+        .send(MusicPlayerMessage::SetTransitions(transitions))
+        .unwrap();
+    music_player_tx
+        .send(MusicPlayerMessage::SetNormalization(normalization))
+        .unwrap();
+    music_player_tx
+        .send(MusicPlayerMessage::SetPlayMode(play_mode))
+        .unwrap();
+    music_player_tx
+        .send(MusicPlayerMessage::SetEqualizer(equalizer))
+        .unwrap();
+    music_player_tx
+        .send(MusicPlayerMessage::SetRate(rate))
+        .unwrap();
+    for entry in queue.iter() {
+        music_player_tx
+            .send(MusicPlayerMessage::AddMusic(entry.clone()))
+            .unwrap();
+    }
+    let resolvers = Resolvers::from_config(&config, &conn);
     let app_state = Arc::new(AppState {
-        list: Mutex::new(vec![]),
+        list: Mutex::new(queue),
         tx,
         conn,
         music_player_tx,
+        volume: Mutex::new(volume),
+        transitions: Mutex::new(transitions),
+        normalization: Mutex::new(normalization),
+        play_mode: Mutex::new(play_mode),
+        equalizer: Mutex::new(equalizer),
+        rate: Mutex::new(rate),
+        output: Mutex::new(output),
+        stream: Arc::new(AudioStream::default()),
+        autoplay: Autoplay::default(),
+        cache: AudioCache::default(),
+        resolvers,
+        config,
     });
 
     music_player::music_player(rx1, app_state.clone());
 
+    // Local music library
+    let library_state = app_state.clone();
+    tokio::spawn(async move {
+        let dirs = library_state.config.library_dirs.clone();
+        if let Err(err) = library::scan(&library_state.conn, dirs).await {
+            log::error!("Error scanning the library: {err}");
+        }
+        // The tracks are playable before their loudness is measured
+        if let Err(err) = library::measure_tracks(&library_state.conn).await {
+            log::error!("Error measuring the library: {err}");
+        }
+    });
+
     // Axum web server
     let app = Router::new()
         .route("/", get(|| async { Redirect::permanent("/index") }))
@@ -83,6 +247,12 @@ async fn main() {
         .fallback_service(tower::service_fn(fallback_service_fn))
         .route("/websocket", get(websocket::websocket_handler))
         .route("/api/playlist", get(playlist))
+        .route("/api/history", get(history::history_handler))
+        .route("/stream", get(stream::stream_handler))
+        .route(
+            "/api/admin/output",
+            get(admin::get_output).put(admin::set_output),
+        )
         .with_state(app_state);
 
     let addr = SocketAddr::from_str("127.0.0.1:4000").unwrap();
@@ -94,8 +264,111 @@ async fn main() {
         .unwrap();
 }
 
+/// Fades set before the last shutdown
+async fn load_transitions(conn: &DatabaseConnection) -> Transitions {
+    let mut transitions = Transitions::default();
+    for (key, value, max) in [
+        (
+            sql::setting::CROSSFADE,
+            &mut transitions.crossfade_ms,
+            Transitions::MAX_CROSSFADE_MS,
+        ),
+        (
+            sql::setting::FADE,
+            &mut transitions.fade_ms,
+            Transitions::MAX_FADE_MS,
+        ),
+    ] {
+        match sql::setting::get(conn, key).await {
+            Ok(Some(saved)) => {
+                if let Ok(saved) = saved.parse::<u32>() {
+                    *value = saved.min(max);
+                }
+            }
+            Ok(None) => (),
+            Err(err) => log::error!("Error loading the {key} setting: {err}"),
+        }
+    }
+    transitions
+}
+
+/// Loudness normalization set before the last shutdown
+async fn load_normalization(conn: &DatabaseConnection) -> Normalization {
+    let mut normalization = Normalization::default();
+    match sql::setting::get(conn, sql::setting::NORMALIZATION_ENABLED).await {
+        Ok(Some(enabled)) => normalization.enabled = enabled.parse().unwrap_or(true),
+        Ok(None) => (),
+        Err(err) => log::error!("Error loading the normalization setting: {err}"),
+    }
+    match sql::setting::get(conn, sql::setting::NORMALIZATION_TARGET).await {
+        Ok(Some(target)) => {
+            if let Ok(target) = target.parse::<f64>()
+                && target.is_finite()
+            {
+                normalization.target_lufs = target.clamp(
+                    Normalization::MIN_TARGET_LUFS,
+                    Normalization::MAX_TARGET_LUFS,
+                );
+            }
+        }
+        Ok(None) => (),
+        Err(err) => log::error!("Error loading the normalization target: {err}"),
+    }
+    normalization
+}
+
+/// Repeat and shuffle modes set before the last shutdown
+async fn load_play_mode(conn: &DatabaseConnection) -> PlayMode {
+    let mut play_mode = PlayMode::default();
+    match sql::setting::get(conn, sql::setting::REPEAT).await {
+        Ok(Some(repeat)) => play_mode.repeat = Repeat::parse(&repeat).unwrap_or_default(),
+        Ok(None) => (),
+        Err(err) => log::error!("Error loading the repeat mode: {err}"),
+    }
+    match sql::setting::get(conn, sql::setting::SHUFFLE).await {
+        Ok(Some(shuffle)) => play_mode.shuffle = shuffle.parse().unwrap_or_default(),
+        Ok(None) => (),
+        Err(err) => log::error!("Error loading the shuffle mode: {err}"),
+    }
+    play_mode
+}
+
+/// Equalizer set before the last shutdown, a preset uses the gains of this build
+async fn load_equalizer(conn: &DatabaseConnection) -> Equalizer {
+    let preset = match sql::setting::get(conn, sql::setting::EQUALIZER_PRESET).await {
+        Ok(preset) => preset
+            .and_then(|preset| EqualizerPreset::parse(&preset))
+            .unwrap_or_default(),
+        Err(err) => {
+            log::error!("Error loading the equalizer preset: {err}");
+            EqualizerPreset::default()
+        }
+    };
+    let mut equalizer = Equalizer::preset(preset);
+    if preset != EqualizerPreset::Custom {
+        return equalizer;
+    }
+    match sql::setting::get(conn, sql::setting::EQUALIZER_GAINS).await {
+        Ok(Some(gains)) => {
+            let gains: Vec<f64> = gains
+                .split(',')
+                .filter_map(|gain| gain.parse::<f64>().ok())
+                .filter(|gain| gain.is_finite())
+                .collect();
+            if gains.len() == EQUALIZER_BANDS {
+                for (gain_db, saved) in equalizer.gains_db.iter_mut().zip(gains) {
+                    *gain_db = saved.clamp(Equalizer::MIN_GAIN_DB, Equalizer::MAX_GAIN_DB);
+                }
+            }
+        }
+        Ok(None) => (),
+        Err(err) => log::error!("Error loading the equalizer gains: {err}"),
+    }
+    equalizer
+}
+
 #[axum::debug_handler]
-async fn playlist(State(app_state): State<Arc<AppState>>) -> Json<Vec<video::Model>> {
+async fn playlist(State(app_state): State<Arc<AppState>>) -> Json<Vec<QueueEntry>> {
     log::info!("Get /api/playlist");
     let playlist = app_state.list.lock().await;
     Json(playlist.clone())
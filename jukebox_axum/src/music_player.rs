@@ -1,21 +1,180 @@
-use crate::AppState;
-use entity::video;
+pub mod cache;
+mod deck;
+pub mod resolver;
+pub mod sink;
+
+use crate::stream::AudioStream;
+use crate::{autoplay, sql, AppState};
+use deck::Deck;
+use entity::video::Model as Video;
+use futures::stream::BoxStream;
 use futures::StreamExt;
-use gstreamer::prelude::{ElementExt, ObjectExt};
-use gstreamer::{glib, MessageView, State};
-use jukebox_rust::NetData;
-use std::sync::Arc;
+use gstreamer::prelude::{Cast, ElementExt, ElementExtManual, GstBinExt, ObjectExt};
+use gstreamer::{
+    glib, message, tags, ClockTime, Element, Message, MessageView, Pipeline, ResourceError, State,
+};
+use jukebox_rust::{
+    EntryId, Equalizer, ErrorKind, NetData, Normalization, PlayMode, PlayOutcome, PlaybackState,
+    QueueEntry, Repeat, Transitions,
+};
+use rand_core::{OsRng, RngCore};
+use resolver::{Audio, REPLAYGAIN_REFERENCE_LUFS};
+use sink::OutputSink;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::oneshot;
 use tracing::log;
 
+const SAVE_POSITION_INTERVAL: Duration = Duration::from_secs(5);
+const NOW_PLAYING_INTERVAL: Duration = Duration::from_secs(1);
+const FADE_INTERVAL: Duration = Duration::from_millis(25);
+/// Number of entries at the head of the queue whose uri is resolved in advance
+const PREFETCH_COUNT: usize = 2;
+/// Uris expiring sooner than this are resolved again before being played
+const EXPIRE_MARGIN: Duration = Duration::from_secs(10 * 60);
+/// Bounds of the normalization gain, so that a wrong loudness does not make a music inaudible or saturated
+const MIN_GAIN_DB: f64 = -20.0;
+const MAX_GAIN_DB: f64 = 10.0;
+/// Minimal time between two restarts of a failing output, so that a missing device does not restart it endlessly
+const OUTPUT_RESTART_DELAY: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 pub enum MusicPlayerMessage {
     SetVolume(f64),
-    AddMusic(video::Model),     // (video, APPEND_PLAY)
-    RemoveVideo(usize, String), // Index and Id of the video in the playlist
-    Move(usize, String, i32),
+    AddMusic(QueueEntry),
+    RemoveVideo(EntryId),
+    Move(EntryId, i32),     // Entry | Delta of the move
+    MoveTo(EntryId, usize), // Entry | New index in the playlist
+    Reorder(Vec<EntryId>),  // Entries of the playlist in their new order
     Play,
     Pause,
+    Status(oneshot::Sender<PlayerStatus>),
+    Seek(u64), // Position in milliseconds
+    Skip,
+    Resolved(EntryId, Result<Audio, String>), // Entry | Music or why it cannot be played
+    SetTransitions(Transitions),
+    SetNormalization(Normalization),
+    SetEqualizer(Equalizer),
+    SetRate(f64),
+    SetEntryRate(EntryId, Option<f64>), // Entry | Its own playback rate, None for the global one
+    SetOutput(OutputSink, oneshot::Sender<Result<(), String>>), // Sink | Whether the sink could be opened
+    SetPlayMode(PlayMode),
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerStatus {
+    pub position_ms: u64,
+    pub paused: bool,
+}
+
+/// Audio of a music of the playlist
+#[derive(Debug, Clone)]
+enum Source {
+    Pending,
+    Resolving,
+    Ready(Audio),
+}
+
+/// Music the active deck switches to by itself at the end of the current one
+#[derive(Default)]
+struct Handover {
+    active: usize,                   // Deck playing the current music
+    next: Option<(EntryId, String)>, // Entry following the current one, if its uri is known
+    pending: Option<EntryId>,        // Entry given to the deck that has not started yet
+}
+
+/// Music of the active deck, saved in the history when it ends
+struct Play {
+    entry: QueueEntry,
+    started_at: i64, // Unix timestamp in seconds
+}
+
+struct MusicPlayer {
+    output: Pipeline,
+    output_restarted: Option<Instant>,
+    volume: f64,      // Volume of the output between 0 and 100
+    decks: [Deck; 2], // The two decks overlap during a crossfade
+    active: usize,
+    app_state: Arc<AppState>,
+    playlist: Vec<(QueueEntry, Source)>,
+    handover: Arc<Mutex<Handover>>,
+    transitions: Transitions,
+    normalization: Normalization,
+    play_mode: PlayMode,
+    equalizer: Equalizer,
+    rate: f64,                // Playback rate of the entries without their own one
+    retried: Option<EntryId>, // Last entry resolved again because its uri was rejected, it is not retried twice
+    buffering: bool,          // The active deck is paused until its buffer is filled
+    played: Option<Play>,
+}
+
+/// Deck given the next music before the end of the current one so that there is no gap between them
+fn new_deck(index: usize, handover: &Arc<Mutex<Handover>>) -> Deck {
+    let deck = Deck::new(index);
+    // Called from a streaming thread
+    let handover_signal = handover.clone();
+    deck.playbin.connect("about-to-finish", false, move |args| {
+        let mut handover = handover_signal.lock().unwrap();
+        if handover.active != index {
+            return None;
+        }
+        if let Some((entry_id, uri)) = handover.next.take()
+            && let Ok(playbin) = args[0].get::<Element>()
+        {
+            log::info!("Queueing music: {}", uri);
+            playbin.set_property("uri", uri);
+            handover.pending = Some(entry_id);
+        }
+        None
+    });
+    deck
+}
+
+/// Events of the playbin of a deck, tagged with the deck
+fn deck_messages(deck: &Deck, index: usize) -> BoxStream<'static, (Option<usize>, Message)> {
+    deck.playbin
+        .bus()
+        .unwrap()
+        .stream()
+        .map(move |msg| (Some(index), msg))
+        .boxed()
+}
+
+/// The decks are mixed into a single output so that a music can fade in while the previous one fades out,
+/// the equalizer applies to the mix and the output is also encoded for the listeners of the stream,
+/// the sink does not wait for them
+fn output_description(sink: &OutputSink) -> String {
+    format!(
+        "audiomixer name=mixer ! audioconvert ! equalizer-10bands name=equalizer ! volume name=master ! tee name=split \
+         split. ! queue ! {} \
+         split. ! queue leaky=downstream ! audioconvert ! audioresample ! opusenc ! oggmux ! appsink name=stream sync=false \
+         interaudiosrc channel={} ! audioconvert ! audioresample ! mixer. \
+         interaudiosrc channel={} ! audioconvert ! audioresample ! mixer.",
+        sink.description(),
+        deck::channel(0),
+        deck::channel(1),
+    )
+}
+
+fn build_output(sink: &OutputSink, stream: &Arc<AudioStream>) -> Result<Pipeline, glib::Error> {
+    let output = gstreamer::parse_launch(&output_description(sink))?
+        .downcast::<Pipeline>()
+        .unwrap();
+    if let Some(appsink) = output.by_name("stream") {
+        stream.connect(appsink);
+    }
+    Ok(output)
+}
+
+/// Events of the output pipeline, they are not tagged with a deck
+fn output_messages(output: &Pipeline) -> BoxStream<'static, (Option<usize>, Message)> {
+    output
+        .bus()
+        .unwrap()
+        .stream()
+        .map(|msg| (None, msg))
+        .boxed()
 }
 
 // Reference : https://gitlab.freedesktop.org/gstreamer/gstreamer-rs/-/blob/7dc5a90b8ab45593d2461850d274ce8ca84891fe/examples/src/bin/glib-futures.rs
@@ -25,92 +184,242 @@ pub fn music_player(mut rx: UnboundedReceiver<MusicPlayerMessage>, app_state: Ar
     let main_loop = glib::MainLoop::new(Some(&ctx), false);
     gstreamer::init().expect("gstreamer initialization failed");
 
-    // Used to play music
-    let pipeline = gstreamer::parse_launch("playbin").unwrap();
-    // Used to receive events of the pipeline
-    let bus = pipeline.bus().unwrap();
+    let handover = Arc::new(Mutex::new(Handover::default()));
+    let decks = [new_deck(0, &handover), new_deck(1, &handover)];
+    // Used to receive events of the pipelines, tagged with the deck sending them
+    let messages_decks = [deck_messages(&decks[0], 0), deck_messages(&decks[1], 1)];
 
     // Spawn a new thread with tokio so that is have a tokio reactor (std::thread::spawn will not work here)
     tokio::task::spawn_blocking(move || {
         ctx.spawn_local(async move {
-            let mut music_player_playlist: Vec<(String, String)> = vec![]; // Id of the video | Uri of the music
-            let mut messages = bus.stream();
+            // Used to play music
+            let mut sink = app_state.output.lock().await;
+            let output = build_output(&sink, &app_state.stream).unwrap_or_else(|err| {
+                log::error!("Cannot open the output {sink:?}: {err}, using the default one");
+                *sink = OutputSink::default();
+                build_output(&sink, &app_state.stream).unwrap()
+            });
+            drop(sink);
+            output.set_state(State::Playing).unwrap();
+            let messages_output = output_messages(&output);
+            let mut player = MusicPlayer {
+                output,
+                output_restarted: None,
+                volume: 100.0,
+                decks,
+                active: 0,
+                app_state: app_state.clone(),
+                playlist: vec![],
+                handover,
+                transitions: Transitions::default(),
+                normalization: Normalization::default(),
+                play_mode: PlayMode::default(),
+                equalizer: Equalizer::default(),
+                rate: 1.0,
+                retried: None,
+                buffering: false,
+                played: None,
+            };
+            // A pipeline that is replaced adds its new messages to the stream
+            let mut messages = futures::stream::select_all(messages_decks.into_iter().chain([messages_output]));
+            // Position in the first music of the queue when the server was stopped
+            let resume_position = match sql::setting::get(&app_state.conn, sql::setting::PLAYBACK_POSITION).await {
+                Ok(position) => position.and_then(|p| p.parse::<u64>().ok()).filter(|p| *p > 0),
+                Err(err) => {
+                    log::error!("Error loading the playback position: {err}");
+                    None
+                }
+            };
+            let mut resume_position = app_state.list.lock().await.first().map(|e| e.id).zip(resume_position);
+            let mut save_position_interval = tokio::time::interval(SAVE_POSITION_INTERVAL);
+            let mut now_playing_interval = tokio::time::interval(NOW_PLAYING_INTERVAL);
+            let mut fade_interval = tokio::time::interval(FADE_INTERVAL);
             loop {
                 tokio::select! {
                     msg1_opt = rx.recv() => {
                         if let Some(msg) = msg1_opt {
                             match msg {
                                 MusicPlayerMessage::SetVolume(volume) => {
-                                    pipeline.set_property("volume", (volume / 100.0).clamp(0.0, 1.0));
-                                }
-                                MusicPlayerMessage::AddMusic(video) => {
-                                    if let Ok(video_data) = my_youtube_extractor::get_best_audio(&video.id).await {
-                                        let uri = video_data.url;
-                                        if music_player_playlist.is_empty() {
-                                            log::info!("Playing music: {}", uri);
-                                            pipeline.set_state(State::Null).unwrap();
-                                            pipeline.set_property("uri", uri.clone());
-                                            pipeline.set_state(State::Playing).unwrap();
-                                        }
-                                        music_player_playlist.push((video.id, uri));
+                                    player.volume = volume;
+                                    player.apply_volume();
+                                }
+                                MusicPlayerMessage::SetOutput(sink, reply) => {
+                                    let result = player.set_output(&sink).map(|output_messages| messages.push(output_messages));
+                                    let _ = reply.send(result);
+                                }
+                                MusicPlayerMessage::SetTransitions(transitions) => {
+                                    player.transitions = transitions;
+                                    // The handover is only used for gapless playback
+                                    player.sync(Duration::ZERO);
+                                }
+                                MusicPlayerMessage::SetPlayMode(play_mode) => {
+                                    player.play_mode = play_mode;
+                                    // The music given at the end of the current one depends on the repeat mode
+                                    player.sync(Duration::ZERO);
+                                }
+                                MusicPlayerMessage::SetNormalization(normalization) => {
+                                    player.normalization = normalization;
+                                    for index in 0..player.decks.len() {
+                                        player.apply_gain(index);
                                     }
                                 }
-                                MusicPlayerMessage::RemoveVideo(index, video_id) => {
-                                    if let Some((local_video_id, _)) = music_player_playlist.first() && local_video_id == &video_id {
-                                        music_player_playlist.remove(index);
-                                        if index == 0 {
-                                            if let Some((_, uri)) = music_player_playlist.first() {
-                                                log::info!("Playing music after remove: {}", uri);
-                                                pipeline.set_state(State::Null).unwrap();
-                                                pipeline.set_property("uri", uri.clone());
-                                                pipeline.set_state(State::Playing).unwrap();
-                                            }
-                                            else {
-                                                pipeline.set_state(State::Null).unwrap();
+                                MusicPlayerMessage::SetEqualizer(equalizer) => {
+                                    player.equalizer = equalizer;
+                                    player.apply_equalizer();
+                                }
+                                MusicPlayerMessage::SetRate(rate) => {
+                                    player.rate = rate;
+                                    player.apply_rate();
+                                }
+                                MusicPlayerMessage::SetEntryRate(entry_id, rate) => {
+                                    if let Some((entry, _)) = player.playlist.iter_mut().find(|(entry, _)| entry.id == entry_id) {
+                                        entry.rate = rate;
+                                        player.apply_rate();
+                                    }
+                                }
+                                MusicPlayerMessage::AddMusic(entry) => {
+                                    player.playlist.push((entry, Source::Pending));
+                                    player.sync(player.fade());
+                                }
+                                MusicPlayerMessage::Resolved(entry_id, result) => {
+                                    match result {
+                                        Ok(audio) => {
+                                            if let Some((_, source)) = player.playlist.iter_mut().find(|(entry, _)| entry.id == entry_id) {
+                                                *source = Source::Ready(audio);
                                             }
                                         }
+                                        Err(err) => player.drop_unplayable(entry_id, err).await,
+                                    }
+                                    player.sync(player.fade());
+                                }
+                                MusicPlayerMessage::RemoveVideo(entry_id) => {
+                                    if let Some(index) = player.playlist.iter().position(|(entry, _)| entry.id == entry_id) {
+                                        let (entry, _) = player.playlist.remove(index);
+                                        player.sync(player.fade());
+                                        player.autoplay_when_dry(entry.video);
                                     }
                                     else {
                                         log::error!("Trying to remove a video that is not in the playlist");
                                     }
                                 }
-                                MusicPlayerMessage::Move(index, video_id, delta) => {
-                                    if index as i32 + delta >= 0 && index as i32 + delta < music_player_playlist.len() as i32 && let Some((loval_video_id, _)) = music_player_playlist.get(index) && loval_video_id == &video_id {
-                                        music_player_playlist.swap(index, (index as i32 + delta) as usize);
-                                        if index == 0 || (index as i32 + delta) as usize == 0 {
-                                            if let Some((_, uri)) = music_player_playlist.first() {
-                                                pipeline.set_state(State::Null).unwrap();
-                                                pipeline.set_property("uri", uri.clone());
-                                                pipeline.set_state(State::Playing).unwrap();
-                                            }
-                                        }
+                                MusicPlayerMessage::Move(entry_id, delta) => {
+                                    if let Some(index) = player.playlist.iter().position(|(entry, _)| entry.id == entry_id) && let Some(other_index) = index.checked_add_signed(delta as isize) && other_index < player.playlist.len() {
+                                        player.playlist.swap(index, other_index);
+                                        player.sync(player.fade());
+                                    }
+                                }
+                                MusicPlayerMessage::MoveTo(entry_id, new_index) => {
+                                    if let Some(index) = player.playlist.iter().position(|(entry, _)| entry.id == entry_id) {
+                                        let moved = player.playlist.remove(index);
+                                        player.playlist.insert(new_index.min(player.playlist.len()), moved);
+                                        player.sync(player.fade());
                                     }
                                 }
+                                MusicPlayerMessage::Reorder(order) => {
+                                    player.playlist.sort_by_key(|(entry, _)| order.iter().position(|id| *id == entry.id).unwrap_or(usize::MAX));
+                                    player.sync(player.fade());
+                                }
                                 MusicPlayerMessage::Play => {
-                                    pipeline.set_state(State::Playing).unwrap();
+                                    player.buffering = false;
+                                    let fade = player.fade();
+                                    player.decks[player.active].resume(fade);
                                 }
                                 MusicPlayerMessage::Pause => {
-                                    pipeline.set_state(State::Paused).unwrap();
+                                    // The end of the buffering must not resume the music
+                                    player.buffering = false;
+                                    let fade = player.fade();
+                                    player.decks[player.active].pause(fade);
+                                }
+                                MusicPlayerMessage::Seek(position) => {
+                                    log::info!("Seeking music at {position}ms");
+                                    if let Err(err) = player.decks[player.active].seek(ClockTime::from_mseconds(position)) {
+                                        log::error!("Error seeking music: {err}");
+                                    }
+                                }
+                                MusicPlayerMessage::Skip => {
+                                    log::info!("Skipping music");
+                                    // The saved position belongs to the skipped music
+                                    resume_position = None;
+                                    player.history_end(PlayOutcome::Skipped);
+                                    player.play_next(player.fade()).await;
+                                }
+                                MusicPlayerMessage::Status(reply) => {
+                                    let _ = reply.send(PlayerStatus {
+                                        position_ms: player.playbin().query_position::<ClockTime>().map_or(0, |p| p.mseconds()),
+                                        paused: player.playbin().current_state() != State::Playing && !player.buffering,
+                                    });
                                 }
                             }
                         }
                     }
                     msg2_opt = messages.next() => {
-                        if let Some(msg) = msg2_opt {
-                            if let MessageView::Eos(..) = msg.view() { // TODO : Maybe other messages are useful
-                                 let mut playlist_axum = app_state.list.lock().await;
-                                 music_player_playlist.remove(0);
-                                 playlist_axum.remove(0);
-                                 app_state.tx.send(NetData::Next).unwrap();
-                                 if let Some((_, uri)) = music_player_playlist.first() {
-                                     log::info!("Playing music: {}", uri);
-                                     pipeline.set_state(State::Null).unwrap();
-                                     pipeline.set_property("uri", uri.clone());
-                                     pipeline.set_state(State::Playing).unwrap();
-                                 }
-                                 else {
-                                     pipeline.set_state(State::Null).unwrap();
-                                 }
+                        if let Some((_, msg)) = &msg2_opt && let MessageView::Warning(warning) = msg.view() {
+                            log::warn!("Warning from the player: {} ({:?})", warning.error(), warning.debug());
+                        }
+                        if let Some((None, msg)) = &msg2_opt && let MessageView::Error(err) = msg.view() {
+                            player.output_failed(err);
+                        }
+                        // Messages of a deck fading out are not about the current music
+                        if let Some((deck, msg)) = msg2_opt && deck == Some(player.active) {
+                            if let MessageView::AsyncDone(..) = msg.view() {
+                                if let Some((entry_id, position)) = resume_position.take() && player.decks[player.active].entry == Some(entry_id) {
+                                    log::info!("Resuming music at {position}ms");
+                                    if let Err(err) = player.decks[player.active].seek(ClockTime::from_mseconds(position)) {
+                                        log::error!("Error resuming music: {err}");
+                                    }
+                                }
+                                // A new stream plays at the normal speed until it is prerolled
+                                player.decks[player.active].apply_rate();
+                                // Sent after every seek so that the clients get the new position
+                                let _ = app_state.tx.send(player.now_playing());
+                            }
+                            if let MessageView::StateChanged(state_changed) = msg.view() && state_changed.src() == Some(player.playbin().upcast_ref()) {
+                                let _ = app_state.tx.send(player.now_playing());
+                            }
+                            if let MessageView::StreamStart(..) = msg.view() {
+                                player.stream_started().await;
+                            }
+                            // ReplayGain tags are only used for the musics whose loudness is not given by their source
+                            if let MessageView::Tag(tag) = msg.view() && player.decks[player.active].loudness.is_none() && let Some(gain) = tag.tags().get::<tags::TrackGain>() {
+                                player.decks[player.active].loudness = Some(REPLAYGAIN_REFERENCE_LUFS - gain.get());
+                                player.apply_gain(player.active);
+                            }
+                            if let MessageView::Error(err) = msg.view() && let Some(position) = player.stream_failed(err).await {
+                                resume_position = Some(position);
+                            }
+                            if let MessageView::Buffering(buffering) = msg.view() {
+                                player.buffer(buffering.percent());
+                            }
+                            if let MessageView::Eos(..) = msg.view() {
+                                player.music_ended().await;
+                            }
+                        }
+                    }
+                    _ = fade_interval.tick() => {
+                        for deck in player.decks.iter_mut() {
+                            deck.step();
+                        }
+                        for index in 0..player.decks.len() {
+                            if player.decks[index].is_broken() {
+                                messages.push(player.rebuild_deck(index));
+                            }
+                        }
+                        player.check_crossfade().await;
+                    }
+                    _ = now_playing_interval.tick() => {
+                        if player.playbin().current_state() == State::Playing {
+                            let _ = app_state.tx.send(player.now_playing());
+                        }
+                    }
+                    _ = save_position_interval.tick() => {
+                        // Wait for the position to be restored before overwriting it
+                        if resume_position.is_none() {
+                            let position = match player.playbin().current_state() {
+                                State::Playing | State::Paused => player.playbin().query_position::<ClockTime>().map_or(0, |p| p.mseconds()),
+                                _ => 0,
+                            };
+                            if let Err(err) = sql::setting::set(&app_state.conn, sql::setting::PLAYBACK_POSITION, position.to_string()).await {
+                                log::error!("Error saving the playback position: {err}");
                             }
                         }
                     }
@@ -121,3 +430,524 @@ pub fn music_player(mut rx: UnboundedReceiver<MusicPlayerMessage>, app_state: Ar
         main_loop.run();
     });
 }
+
+impl MusicPlayer {
+    /// Playbin of the active deck
+    fn playbin(&self) -> &Element {
+        &self.decks[self.active].playbin
+    }
+
+    /// Duration of the fades on pause, resume and skip
+    fn fade(&self) -> Duration {
+        Duration::from_millis(self.transitions.fade_ms as u64)
+    }
+
+    /// Make the active deck play the first music of the playlist and prepare the following one,
+    /// a music replaced while playing fades out during `fade`
+    fn sync(&mut self, fade: Duration) {
+        let current = self.decks[self.active].entry;
+        // Uris about to expire are resolved again right before being played
+        for (entry, source) in self.playlist.iter_mut().take(PREFETCH_COUNT) {
+            if let Source::Ready(audio) = source
+                && current != Some(entry.id)
+                && audio.expires_within(EXPIRE_MARGIN)
+            {
+                log::info!("Uri of {} expired, resolving it again", entry.video.id);
+                *source = Source::Pending;
+            }
+        }
+        self.prefetch();
+        match self.playlist.first() {
+            Some((entry, _)) if current == Some(entry.id) => (),
+            Some((entry, Source::Ready(audio))) => {
+                let (entry_id, audio, rate) = (entry.id, audio.clone(), self.rate_of(entry));
+                // The other deck takes over while the current music fades out, the same goes for
+                // a music stopped while the uri of the next one was resolved and still fading out
+                if current.is_some() {
+                    self.decks[self.active].fade_out(fade);
+                }
+                if current.is_some() || self.decks[self.active].is_fading() {
+                    self.active = 1 - self.active;
+                }
+                log::info!("Playing music: {}", audio.uri);
+                self.buffering = false;
+                self.decks[self.active].loudness = audio.loudness;
+                self.apply_gain(self.active);
+                self.decks[self.active].play(entry_id, &audio.uri, fade, rate);
+                self.handover.lock().unwrap().pending = None;
+                self.history_start(entry_id);
+            }
+            // Nothing to play or waiting for the uri of the first music
+            _ => {
+                if current.is_some() {
+                    self.decks[self.active].fade_out(fade);
+                    self.history_end(PlayOutcome::Skipped);
+                }
+            }
+        }
+
+        // A music repeated is given again to the deck
+        let next_index = if self.play_mode.repeat == Repeat::One {
+            0
+        } else {
+            1
+        };
+        let next = match self.playlist.get(next_index) {
+            Some((entry, Source::Ready(audio)))
+                if self.decks[self.active].entry.is_some()
+                    && self.transitions.crossfade_ms == 0 =>
+            {
+                Some((entry.id, audio.uri.clone()))
+            }
+            _ => None,
+        };
+        let mut handover = self.handover.lock().unwrap();
+        handover.active = self.active;
+        handover.next = next;
+    }
+
+    /// Resolve the uri of the first musics of the playlist in advance
+    fn prefetch(&mut self) {
+        for (entry, source) in self.playlist.iter_mut().take(PREFETCH_COUNT) {
+            if let Source::Pending = source {
+                *source = Source::Resolving;
+                let app_state = self.app_state.clone();
+                let entry = entry.clone();
+                tokio::spawn(async move {
+                    let result = cache::resolve(&app_state, &entry.video)
+                        .await
+                        .map_err(|err| err.to_string());
+                    let _ = app_state
+                        .music_player_tx
+                        .send(MusicPlayerMessage::Resolved(entry.id, result));
+                });
+            }
+        }
+    }
+
+    /// Drop the music playing from the queue and start the following one, used at the end of a music and to skip it
+    async fn play_next(&mut self, fade: Duration) {
+        if self.playlist.is_empty() {
+            return;
+        }
+        self.finish_current().await;
+        // The only music of a repeated queue starts again
+        if self.playlist.first().map(|(entry, _)| entry.id) == self.decks[self.active].entry {
+            self.decks[self.active].stop();
+        }
+        self.sync(fade);
+    }
+
+    /// The music playing reached its end
+    async fn music_ended(&mut self) {
+        self.history_end(PlayOutcome::Finished);
+        self.decks[self.active].stop();
+        if self.play_mode.repeat == Repeat::One {
+            self.sync(Duration::ZERO);
+        } else {
+            self.play_next(Duration::ZERO).await;
+        }
+    }
+
+    /// Start the next music on the other deck when the current one is about to end
+    async fn check_crossfade(&mut self) {
+        let crossfade = Duration::from_millis(self.transitions.crossfade_ms as u64);
+        let deck = &self.decks[self.active];
+        if crossfade.is_zero()
+            || self.play_mode.repeat == Repeat::One
+            || deck.entry.is_none()
+            || deck.is_fading()
+            || deck.playbin.current_state() != State::Playing
+            || !matches!(self.playlist.get(1), Some((_, Source::Ready(_))))
+        {
+            return;
+        }
+        let (Some(position), Some(duration)) = (
+            deck.playbin.query_position::<ClockTime>(),
+            deck.playbin.query_duration::<ClockTime>(),
+        ) else {
+            return;
+        };
+        // The music lasts less than its duration when it is sped up
+        let remaining_ms =
+            duration.mseconds().saturating_sub(position.mseconds()) as f64 / deck.rate();
+        if remaining_ms <= crossfade.as_millis() as f64 {
+            log::info!("Crossfading to the next music");
+            self.history_end(PlayOutcome::Finished);
+            self.play_next(crossfade).await;
+        }
+    }
+
+    /// The active deck started a new stream, it is the next music when it was given at the end of the previous one
+    async fn stream_started(&mut self) {
+        let pending = self.handover.lock().unwrap().pending.take();
+        if let Some(entry_id) = pending {
+            self.history_end(PlayOutcome::Finished);
+            let current = self.decks[self.active].entry;
+            // A repeated music is given again to the deck, it stays the current one
+            if current.is_some()
+                && current != Some(entry_id)
+                && self.playlist.first().map(|(entry, _)| entry.id) == current
+            {
+                self.finish_current().await;
+            }
+            self.decks[self.active].entry = Some(entry_id);
+            self.decks[self.active].loudness = self
+                .playlist
+                .iter()
+                .find(|(entry, _)| entry.id == entry_id)
+                .and_then(|(_, source)| match source {
+                    Source::Ready(audio) => audio.loudness,
+                    _ => None,
+                });
+            self.apply_gain(self.active);
+            let rate = self
+                .playlist
+                .iter()
+                .find(|(entry, _)| entry.id == entry_id)
+                .map_or(self.rate, |(entry, _)| self.rate_of(entry));
+            self.decks[self.active].restart_rate(rate);
+            self.history_start(entry_id);
+            // The playlist may have changed since the music was given to the deck
+            self.sync(self.fade());
+        }
+    }
+
+    /// The active deck cannot play its music, an uri rejected by the server has most likely expired
+    /// so it is resolved once more, returns the position to resume the music at in that case
+    async fn stream_failed(&mut self, err: &message::Error) -> Option<(EntryId, u64)> {
+        let message = err.error().to_string();
+        log::error!("Error playing music: {message} ({:?})", err.debug());
+        let current = self.decks[self.active].entry;
+        let pending = self.handover.lock().unwrap().pending.take();
+        self.buffering = false;
+        let (entry_id, position) = match pending {
+            // The music given to the deck at the end of the previous one could not start,
+            // the previous one ended now and the failure is recorded for the music that could not start
+            Some(entry_id) => {
+                self.history_end(PlayOutcome::Finished);
+                if current.is_some()
+                    && current != Some(entry_id)
+                    && self.playlist.first().map(|(entry, _)| entry.id) == current
+                {
+                    self.finish_current().await;
+                }
+                self.history_start(entry_id);
+                (entry_id, 0)
+            }
+            None => (
+                current?,
+                self.playbin()
+                    .query_position::<ClockTime>()
+                    .map_or(0, |p| p.mseconds()),
+            ),
+        };
+        self.decks[self.active].stop();
+
+        if is_rejected(err)
+            && self.retried != Some(entry_id)
+            && let Some((_, source)) = self
+                .playlist
+                .iter_mut()
+                .find(|(entry, _)| entry.id == entry_id)
+        {
+            log::info!("Uri rejected, resolving it again");
+            self.retried = Some(entry_id);
+            *source = Source::Pending;
+            self.sync(Duration::ZERO);
+            return Some((entry_id, position));
+        }
+        self.history_end(PlayOutcome::Failed);
+        self.drop_unplayable(entry_id, message).await;
+        self.sync(Duration::ZERO);
+        None
+    }
+
+    /// Pause the active deck while its stream is buffering and resume it once the buffer is full
+    fn buffer(&mut self, percent: i32) {
+        let deck = &mut self.decks[self.active];
+        let playing = deck.playbin.current_state() == State::Playing
+            || deck.playbin.pending_state() == State::Playing;
+        if percent < 100 && !self.buffering && playing {
+            log::info!("Buffering music");
+            self.buffering = true;
+            deck.set_state(State::Paused);
+        } else if percent >= 100 && self.buffering {
+            log::info!("Buffering done");
+            self.buffering = false;
+            deck.set_state(State::Playing);
+        }
+    }
+
+    /// Replace a deck whose playbin is stuck by a new one, returns the messages of the new deck
+    fn rebuild_deck(&mut self, index: usize) -> BoxStream<'static, (Option<usize>, Message)> {
+        log::error!("Deck {index} is stuck, replacing it");
+        // Nothing is received from the old playbin anymore
+        if let Some(bus) = self.decks[index].playbin.bus() {
+            bus.set_flushing(true);
+        }
+        self.decks[index] = new_deck(index, &self.handover);
+        let messages = deck_messages(&self.decks[index], index);
+        if index == self.active {
+            // The music of the deck starts again from the beginning
+            self.handover.lock().unwrap().pending = None;
+            self.buffering = false;
+            self.sync(Duration::ZERO);
+        }
+        messages
+    }
+
+    /// The output pipeline failed, it is restarted so that the next musics can be heard
+    fn output_failed(&mut self, err: &message::Error) {
+        log::error!("Error of the output: {} ({:?})", err.error(), err.debug());
+        if self
+            .output_restarted
+            .is_some_and(|restarted| restarted.elapsed() < OUTPUT_RESTART_DELAY)
+        {
+            return;
+        }
+        self.output_restarted = Some(Instant::now());
+        let _ = self.app_state.tx.send(NetData::Error {
+            request_id: None,
+            kind: ErrorKind::Internal,
+            message: format!("The audio output failed: {}", err.error()),
+        });
+        if let Err(err) = self
+            .output
+            .set_state(State::Null)
+            .and_then(|_| self.output.set_state(State::Playing))
+        {
+            log::error!("Cannot restart the output: {err}");
+        }
+    }
+
+    /// Play the musics on another sink, the current output is kept when the new one cannot be opened,
+    /// returns the messages of the new output
+    fn set_output(
+        &mut self,
+        sink: &OutputSink,
+    ) -> Result<BoxStream<'static, (Option<usize>, Message)>, String> {
+        let output = build_output(sink, &self.app_state.stream)
+            .map_err(|err| format!("Invalid output: {err}"))?;
+        // Both outputs cannot read the decks at the same time
+        let _ = self.output.set_state(State::Null);
+        if let Err(err) = output.set_state(State::Playing) {
+            let _ = output.set_state(State::Null);
+            if let Err(err) = self.output.set_state(State::Playing) {
+                log::error!("Cannot restart the previous output: {err}");
+            }
+            return Err(format!("Cannot open the output: {err}"));
+        }
+        log::info!("Playing on {sink:?}");
+        // Nothing is received from the old output anymore
+        if let Some(bus) = self.output.bus() {
+            bus.set_flushing(true);
+        }
+        self.output = output;
+        self.output_restarted = None;
+        self.apply_volume();
+        self.apply_equalizer();
+        Ok(output_messages(&self.output))
+    }
+
+    fn apply_volume(&self) {
+        if let Some(master) = self.output.by_name("master") {
+            master.set_property("volume", (self.volume / 100.0).clamp(0.0, 1.0));
+        }
+    }
+
+    fn apply_equalizer(&self) {
+        if let Some(equalizer) = self.output.by_name("equalizer") {
+            for (band, gain_db) in self.equalizer.gains_db.iter().enumerate() {
+                equalizer.set_property(&format!("band{band}"), *gain_db);
+            }
+        }
+    }
+
+    /// Playback rate of an entry, its own one or the global one
+    fn rate_of(&self, entry: &QueueEntry) -> f64 {
+        entry.rate.unwrap_or(self.rate)
+    }
+
+    /// Change the speed of the music playing after a change of its rate or of the global one
+    fn apply_rate(&mut self) {
+        let deck = &self.decks[self.active];
+        let Some(rate) = self
+            .playlist
+            .iter()
+            .find(|(entry, _)| Some(entry.id) == deck.entry)
+            .map(|(entry, _)| self.rate_of(entry))
+        else {
+            return;
+        };
+        self.decks[self.active].set_rate(rate);
+    }
+
+    /// Bring the loudness of the music of a deck to the target of the normalization
+    fn apply_gain(&mut self, index: usize) {
+        let gain = match self.decks[index].loudness {
+            Some(loudness) if self.normalization.enabled => {
+                let gain_db =
+                    (self.normalization.target_lufs - loudness).clamp(MIN_GAIN_DB, MAX_GAIN_DB);
+                10f64.powf(gain_db / 20.0)
+            }
+            _ => 1.0,
+        };
+        self.decks[index].set_gain(gain);
+    }
+
+    /// Remove the first music of the playlist from the queue of the clients,
+    /// it goes back to the end of the queue when the whole queue is repeated
+    /// and the autoplay fills the queue when it runs dry
+    async fn finish_current(&mut self) {
+        let (entry, source) = self.playlist.remove(0);
+        let mut playlist_axum = self.app_state.list.lock().await;
+        playlist_axum.retain(|e| e.id != entry.id);
+        if self.play_mode.repeat == Repeat::All {
+            // Anywhere after the music playing
+            let position = if self.play_mode.shuffle && !self.playlist.is_empty() {
+                1 + random_below(self.playlist.len())
+            } else {
+                self.playlist.len()
+            };
+            let position_axum = position.min(playlist_axum.len());
+            playlist_axum.insert(position_axum, entry.clone());
+            if let Err(err) =
+                sql::queue::move_to(&self.app_state.conn, entry.id, position as i32).await
+            {
+                log::error!("Error moving video in the saved queue: {err}");
+            }
+            let _ = self
+                .app_state
+                .tx
+                .send(NetData::MoveTo(entry.id, position as u32));
+            self.playlist.insert(position, (entry, source));
+            return;
+        }
+        if let Err(err) = sql::queue::remove(&self.app_state.conn, entry.id).await {
+            log::error!("Error removing video from the saved queue: {err}");
+        }
+        self.app_state.tx.send(NetData::Next).unwrap();
+        self.autoplay_when_dry(entry.video);
+    }
+
+    /// Queue a music with the autoplay when the queue runs dry, `removed` is the music that just left it
+    fn autoplay_when_dry(&self, removed: Video) {
+        // The autoplay adds a music while the last one is playing so that it follows without a gap
+        if self.playlist.len() <= 1 {
+            let last = self
+                .playlist
+                .last()
+                .map_or(removed, |(last, _)| last.video.clone());
+            autoplay::run(&self.app_state, last);
+        }
+    }
+
+    /// Remove an entry the music player cannot play from the queue of the clients
+    async fn drop_unplayable(&mut self, entry_id: EntryId, err: String) {
+        let Some(index) = self
+            .playlist
+            .iter()
+            .position(|(entry, _)| entry.id == entry_id)
+        else {
+            return;
+        };
+        let (entry, _) = self.playlist.remove(index);
+        log::error!("Cannot play {}: {err}", entry.video.id);
+        let mut playlist_axum = self.app_state.list.lock().await;
+        playlist_axum.retain(|e| e.id != entry_id);
+        if let Err(err) = sql::queue::remove(&self.app_state.conn, entry_id).await {
+            log::error!("Error removing video from the saved queue: {err}");
+        }
+        let _ = self.app_state.tx.send(NetData::Remove(entry_id));
+        let _ = self.app_state.tx.send(NetData::Error {
+            request_id: None,
+            kind: ErrorKind::Unplayable,
+            message: format!("Cannot play {}: {err}", entry.video.title),
+        });
+        self.autoplay_when_dry(entry.video);
+    }
+
+    /// Record the music started by the active deck, the music recorded before was replaced
+    fn history_start(&mut self, entry_id: EntryId) {
+        // The same music started again after an error
+        if self
+            .played
+            .as_ref()
+            .is_some_and(|played| played.entry.id == entry_id)
+        {
+            return;
+        }
+        self.history_end(PlayOutcome::Skipped);
+        self.played = self
+            .playlist
+            .iter()
+            .find(|(entry, _)| entry.id == entry_id)
+            .map(|(entry, _)| Play {
+                entry: entry.clone(),
+                started_at: now_seconds(),
+            });
+    }
+
+    /// Save the music recorded in the history
+    fn history_end(&mut self, outcome: PlayOutcome) {
+        let Some(played) = self.played.take() else {
+            return;
+        };
+        let app_state = self.app_state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = sql::history::record(
+                &app_state.conn,
+                &played.entry,
+                played.started_at,
+                now_seconds(),
+                outcome,
+            )
+            .await
+            {
+                log::error!("Error saving the history: {err}");
+            }
+        });
+    }
+
+    /// Current music with its progress, sent to every client
+    fn now_playing(&self) -> NetData {
+        let state = match self.playbin().current_state() {
+            _ if self.buffering => PlaybackState::Buffering,
+            State::Playing => PlaybackState::Playing,
+            State::Paused => PlaybackState::Paused,
+            _ => PlaybackState::Stopped,
+        };
+        NetData::NowPlaying {
+            entry: self.playlist.first().map(|(entry, _)| entry.clone()),
+            position_ms: self
+                .playbin()
+                .query_position::<ClockTime>()
+                .map_or(0, |p| p.mseconds()),
+            duration_ms: self
+                .playbin()
+                .query_duration::<ClockTime>()
+                .map(|d| d.mseconds()),
+            state,
+        }
+    }
+}
+
+/// Whether the server of the stream refused to send it, as YouTube does with expired uris (HTTP 403)
+fn is_rejected(err: &message::Error) -> bool {
+    err.error().matches(ResourceError::NotAuthorized)
+        || err.debug().is_some_and(|debug| debug.contains("(403)"))
+}
+
+/// Unix timestamp in seconds
+fn now_seconds() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Random number below `n`, used by the shuffle mode
+pub fn random_below(n: usize) -> usize {
+    OsRng.next_u32() as usize % n
+}
@@ -0,0 +1,171 @@
+use super::now_seconds;
+use super::resolver::{Audio, YOUTUBE_REFERENCE_LUFS};
+use crate::{sql, AppState};
+use anyhow::{bail, Result};
+use entity::audio_cache::Model as CachedAudio;
+use entity::video::Model as Video;
+use gstreamer::glib;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tracing::log;
+
+/// Size of the ranges the audios are downloaded by, YouTube throttles the requests asking for a whole stream
+const RANGE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// State of the on-disk cache of the audios, shared by the music player
+#[derive(Default)]
+pub struct AudioCache {
+    downloading: Mutex<HashSet<String>>, // Names of the files being downloaded
+}
+
+/// Audio of a video, played from the cache when it was downloaded before,
+/// the audios of YouTube resolved are downloaded in the background for the next time
+pub async fn resolve(state: &Arc<AppState>, video: &Video) -> Result<Audio> {
+    if state.config.cache_size > 0 {
+        match cached(state, video).await {
+            Ok(Some(audio)) => return Ok(audio),
+            Ok(None) => (),
+            Err(err) => log::error!("Error reading the audio cache: {err}"),
+        }
+    }
+    let audio = state.resolvers.resolve(video).await?;
+    if state.config.cache_size > 0
+        && let Some(itag) = audio.itag
+    {
+        download(state, video.id.clone(), itag, audio.clone());
+    }
+    Ok(audio)
+}
+
+async fn cached(state: &AppState, video: &Video) -> Result<Option<Audio>> {
+    let Some(file) = sql::audio_cache::find(&state.conn, &video.id).await? else {
+        return Ok(None);
+    };
+    let path = state.config.cache_dir.join(&file.file_name);
+    if !tokio::fs::try_exists(&path).await? {
+        log::warn!("{} is missing from the audio cache", path.display());
+        sql::audio_cache::remove(&state.conn, &file.video_id, file.itag).await?;
+        return Ok(None);
+    }
+    sql::audio_cache::set_played(&state.conn, &file.video_id, file.itag, now_seconds()).await?;
+    log::info!("Playing {} from the audio cache", video.id);
+    Ok(Some(Audio {
+        uri: glib::filename_to_uri(tokio::fs::canonicalize(&path).await?, None)?.to_string(),
+        loudness: file.loudness_db.map(|db| YOUTUBE_REFERENCE_LUFS + db),
+        expires_at: None,
+        itag: None,
+    }))
+}
+
+/// Download an audio in the cache, then remove the least recently played files above its size
+fn download(state: &Arc<AppState>, video_id: String, itag: u32, audio: Audio) {
+    // A file name has no `/` as YouTube ids are made of letters, digits, `-` and `_`
+    let file_name = format!("{video_id}_{itag}");
+    if !state
+        .cache
+        .downloading
+        .lock()
+        .unwrap()
+        .insert(file_name.clone())
+    {
+        return;
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        let dir = &state.config.cache_dir;
+        let part = dir.join(format!("{file_name}.part"));
+        let result = async {
+            tokio::fs::create_dir_all(dir).await?;
+            let size = download_file(&audio.uri, &part, state.config.cache_size).await?;
+            tokio::fs::rename(&part, dir.join(&file_name)).await?;
+            sql::audio_cache::save(
+                &state.conn,
+                CachedAudio {
+                    video_id: video_id.clone(),
+                    itag,
+                    file_name: file_name.clone(),
+                    size: size as i64,
+                    loudness_db: audio.loudness.map(|lufs| lufs - YOUTUBE_REFERENCE_LUFS),
+                    played_at: now_seconds(),
+                },
+            )
+            .await?;
+            log::info!("Downloaded {video_id} in the audio cache ({size} bytes)");
+            evict(&state).await
+        }
+        .await;
+        if let Err(err) = result {
+            log::error!("Error downloading {video_id} in the audio cache: {err}");
+            let _ = tokio::fs::remove_file(&part).await;
+        }
+        state.cache.downloading.lock().unwrap().remove(&file_name);
+    });
+}
+
+/// Write the stream at `uri` to `path` range by range, returns its size
+async fn download_file(uri: &str, path: &Path, max_size: u64) -> Result<u64> {
+    let client = reqwest::Client::new();
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut size = 0;
+    loop {
+        let mut response = client
+            .get(uri)
+            .header(RANGE, format!("bytes={size}-{}", size + RANGE_SIZE - 1))
+            .send()
+            .await?;
+        // The previous range ended exactly at the end of the stream
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            break;
+        }
+        response = response.error_for_status()?;
+        let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+        let total = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.rsplit_once('/'))
+            .and_then(|(_, total)| total.parse::<u64>().ok());
+        if total.is_some_and(|total| total > max_size) {
+            bail!("The audio is larger than the cache");
+        }
+
+        let start = size;
+        while let Some(chunk) = response.chunk().await? {
+            size += chunk.len() as u64;
+            if size > max_size {
+                bail!("The audio is larger than the cache");
+            }
+            file.write_all(&chunk).await?;
+        }
+        // A server ignoring the range sends the whole stream at once
+        if !partial || size == start || total.is_some_and(|total| size >= total) {
+            break;
+        }
+    }
+    file.flush().await?;
+    Ok(size)
+}
+
+/// Remove the least recently played files until the cache fits in its size
+async fn evict(state: &AppState) -> Result<()> {
+    let files = sql::audio_cache::least_recently_played(&state.conn).await?;
+    let mut total: u64 = files.iter().map(|file| file.size as u64).sum();
+    for file in files {
+        if total <= state.config.cache_size {
+            break;
+        }
+        log::info!("Removing {} from the audio cache", file.file_name);
+        match tokio::fs::remove_file(state.config.cache_dir.join(&file.file_name)).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => (),
+        }
+        sql::audio_cache::remove(&state.conn, &file.video_id, file.itag).await?;
+        total -= file.size as u64;
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use gstreamer::prelude::{ElementExt, ElementExtManual, ObjectExt};
+use gstreamer::{glib, ClockTime, Element, ElementFactory, SeekFlags, SeekType, State};
+use jukebox_rust::EntryId;
+use std::time::{Duration, Instant};
+use tracing::log;
+
+/// Playbin playing one music at a time into the output pipeline
+pub struct Deck {
+    pub playbin: Element,
+    pub entry: Option<EntryId>, // Entry loaded in the deck, None when it is stopped or fading out
+    pub loudness: Option<f64>,  // Estimated loudness of the music in LUFS
+    level: f64,                 // Volume of the deck between 0 and 1, used for the fades
+    gain: f64,                  // Linear gain normalizing the loudness, applied on top of the level
+    fade: Option<Fade>,
+    broken: bool,       // The playbin cannot be stopped anymore
+    rate: f64,          // Speed of the music, its pitch is kept by scaletempo
+    rate_pending: bool, // The rate is applied by a seek once the stream is prerolled
+}
+
+/// Ramp of the level of a deck
+struct Fade {
+    from: f64,
+    to: f64,
+    start: Instant,
+    duration: Duration,
+    then: Option<State>, // State of the deck once the fade is done
+}
+
+impl Deck {
+    pub fn new(index: usize) -> Self {
+        let playbin = ElementFactory::make("playbin")
+            .name(&format!("deck{index}"))
+            .build()
+            .expect("playbin is missing");
+        let sink = ElementFactory::make("interaudiosink")
+            .property("channel", channel(index))
+            .build()
+            .expect("interaudiosink is missing");
+        playbin.set_property("audio-sink", sink);
+        let filter = ElementFactory::make("scaletempo")
+            .build()
+            .expect("scaletempo is missing");
+        playbin.set_property("audio-filter", filter);
+        Deck {
+            playbin,
+            entry: None,
+            loudness: None,
+            level: 1.0,
+            gain: 1.0,
+            fade: None,
+            broken: false,
+            rate: 1.0,
+            rate_pending: false,
+        }
+    }
+
+    /// Start playing `uri` at `rate` from silence, reaching the full level after `fade_in`
+    pub fn play(&mut self, entry_id: EntryId, uri: &str, fade_in: Duration, rate: f64) {
+        self.set_state(State::Null);
+        self.playbin.set_property("uri", uri);
+        self.entry = Some(entry_id);
+        // A new stream starts at the normal speed, `apply_rate` seeks it to `rate` once it is prerolled
+        self.rate = rate;
+        self.rate_pending = rate != 1.0;
+        self.fade = None;
+        self.set_level(if fade_in.is_zero() { 1.0 } else { 0.0 });
+        self.set_state(State::Playing);
+        self.fade(1.0, fade_in, None);
+    }
+
+    /// Fade the music out and stop the deck, its entry is released right away
+    pub fn fade_out(&mut self, duration: Duration) {
+        self.entry = None;
+        self.fade(0.0, duration, Some(State::Null));
+    }
+
+    pub fn stop(&mut self) {
+        self.entry = None;
+        self.fade = None;
+        self.set_state(State::Null);
+    }
+
+    pub fn pause(&mut self, fade_out: Duration) {
+        self.fade(0.0, fade_out, Some(State::Paused));
+    }
+
+    pub fn resume(&mut self, fade_in: Duration) {
+        if self.entry.is_none() {
+            return;
+        }
+        // A pause still fading out is cancelled from its current level
+        self.fade = None;
+        if self.playbin.current_state() != State::Playing {
+            self.set_level(if fade_in.is_zero() { 1.0 } else { 0.0 });
+        }
+        self.set_state(State::Playing);
+        self.fade(1.0, fade_in, None);
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Change the speed of the music playing
+    pub fn set_rate(&mut self, rate: f64) {
+        if rate != self.rate {
+            self.rate = rate;
+            self.rate_pending = true;
+            self.apply_rate();
+        }
+    }
+
+    /// The music given at the end of the previous one started, it may have kept the previous rate
+    pub fn restart_rate(&mut self, rate: f64) {
+        self.rate_pending = rate != 1.0 || self.rate != 1.0;
+        self.rate = rate;
+        self.apply_rate();
+    }
+
+    /// Seek the music at its position with the rate of the deck, called again once the stream is prerolled
+    pub fn apply_rate(&mut self) {
+        if !self.rate_pending
+            || !matches!(self.playbin.current_state(), State::Playing | State::Paused)
+        {
+            return;
+        }
+        let position = self
+            .playbin
+            .query_position::<ClockTime>()
+            .unwrap_or(ClockTime::ZERO);
+        if let Err(err) = self.seek(position) {
+            log::error!("Cannot set the rate of the deck to {}: {err}", self.rate);
+        }
+    }
+
+    /// Seek the music at `position`, the seeks apply the rate of the deck
+    pub fn seek(&mut self, position: ClockTime) -> Result<(), glib::BoolError> {
+        self.playbin.seek(
+            self.rate,
+            SeekFlags::FLUSH | SeekFlags::KEY_UNIT,
+            SeekType::Set,
+            position,
+            SeekType::None,
+            ClockTime::NONE,
+        )?;
+        self.rate_pending = false;
+        Ok(())
+    }
+
+    pub fn set_gain(&mut self, gain: f64) {
+        self.gain = gain;
+        self.set_level(self.level);
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    /// Whether the playbin is stuck and the deck must be replaced
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Change the state of the playbin, the errors of the music itself are reported on the bus
+    pub fn set_state(&mut self, state: State) {
+        if let Err(err) = self.playbin.set_state(state) {
+            log::error!("Cannot set the deck to {state:?}: {err}");
+            if state == State::Null {
+                self.broken = true;
+            }
+        }
+    }
+
+    /// Move the fade forward, called regularly by the music player
+    pub fn step(&mut self) {
+        let Some(fade) = &self.fade else {
+            return;
+        };
+        let progress = if fade.duration.is_zero() {
+            1.0
+        } else {
+            (fade.start.elapsed().as_secs_f64() / fade.duration.as_secs_f64()).min(1.0)
+        };
+        let (level, then) = (fade.from + (fade.to - fade.from) * progress, fade.then);
+        self.set_level(level);
+        if progress >= 1.0 {
+            self.fade = None;
+            if let Some(state) = then {
+                self.set_state(state);
+            }
+        }
+    }
+
+    fn fade(&mut self, to: f64, duration: Duration, then: Option<State>) {
+        self.fade = Some(Fade {
+            from: self.level,
+            to,
+            start: Instant::now(),
+            duration,
+            then,
+        });
+        // Fades without duration are applied right away
+        if duration.is_zero() {
+            self.step();
+        }
+    }
+
+    fn set_level(&mut self, level: f64) {
+        self.level = level;
+        self.playbin.set_property("volume", level * self.gain);
+    }
+}
+
+/// Name of the inter channel between a deck and the output pipeline
+pub fn channel(index: usize) -> String {
+    format!("jukebox-deck{index}")
+}
@@ -0,0 +1,389 @@
+use crate::config::Config;
+use crate::sql;
+use anyhow::{anyhow, bail, Result};
+use entity::video::Model as Video;
+use gstreamer::glib;
+use sea_orm::DatabaseConnection;
+use std::f64::consts::PI;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TONE_SAMPLE_RATE: u32 = 44100;
+const TONE_DEFAULT_SECONDS: u32 = 10;
+const TONE_MAX_SECONDS: u32 = 600;
+const TONE_MAX_FREQUENCY: u32 = 20000;
+/// Loudness YouTube normalizes its videos to, its `loudnessDb` is relative to it
+pub const YOUTUBE_REFERENCE_LUFS: f64 = -14.0;
+/// Loudness the ReplayGain tags bring the musics to
+pub const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+
+/// Music ready to be played
+#[derive(Debug, Clone)]
+pub struct Audio {
+    pub uri: String,
+    pub loudness: Option<f64>, // Loudness in LUFS, when the source gives it
+    pub expires_at: Option<SystemTime>, // When the uri stops working
+    pub itag: Option<u32>, // YouTube format of the stream, the audios having one are downloaded in the cache
+}
+
+impl Audio {
+    fn new(uri: String) -> Self {
+        Audio {
+            uri,
+            loudness: None,
+            expires_at: None,
+            itag: None,
+        }
+    }
+
+    /// Whether the uri stops working in less than `margin`
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= SystemTime::now() + margin)
+    }
+}
+
+/// Turn the video of a queue entry into an uri `playbin` can play
+#[axum::async_trait]
+pub trait AudioResolver: Send + Sync {
+    /// Whether the source of the video is handled by this resolver
+    fn handles(&self, video: &Video) -> bool;
+
+    async fn resolve(&self, video: &Video) -> Result<Audio>;
+}
+
+/// Resolvers registered in the [`AppState`](crate::AppState), the first one handling a video resolves it
+pub struct Resolvers(Vec<Box<dyn AudioResolver>>);
+
+impl Resolvers {
+    pub fn new() -> Self {
+        Resolvers(vec![])
+    }
+
+    pub fn register(mut self, resolver: impl AudioResolver + 'static) -> Self {
+        self.0.push(Box::new(resolver));
+        self
+    }
+
+    /// Whether a registered resolver can play the video
+    pub fn handles(&self, video: &Video) -> bool {
+        self.0.iter().any(|r| r.handles(video))
+    }
+
+    pub async fn resolve(&self, video: &Video) -> Result<Audio> {
+        let resolver = self
+            .0
+            .iter()
+            .find(|r| r.handles(video))
+            .ok_or_else(|| anyhow!("No resolver for {}", video.id))?;
+        resolver.resolve(video).await
+    }
+}
+
+impl Resolvers {
+    /// Resolvers of the sources enabled in the configuration, the server only fetches the HTTP streams
+    /// of the allowed hosts and writes test tones when asked to
+    pub fn from_config(config: &Config, conn: &DatabaseConnection) -> Self {
+        let mut resolvers = Resolvers::new().register(LocalFileResolver { conn: conn.clone() });
+        if !config.http_hosts.is_empty() {
+            resolvers = resolvers.register(HttpResolver {
+                hosts: config.http_hosts.clone(),
+            });
+        }
+        if config.test_tones {
+            resolvers = resolvers.register(ToneResolver {
+                dir: config.cache_dir.clone(),
+            });
+        }
+        // YouTube comes last as its ids have no prefix
+        resolvers.register(YoutubeResolver)
+    }
+}
+
+/// Id of a YouTube video, resolved to its best audio stream
+pub struct YoutubeResolver;
+
+#[axum::async_trait]
+impl AudioResolver for YoutubeResolver {
+    /// YouTube ids are made of letters, digits, `-` and `_`
+    fn handles(&self, video: &Video) -> bool {
+        !video.id.is_empty()
+            && video
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    async fn resolve(&self, video: &Video) -> Result<Audio> {
+        let audio = my_youtube_extractor::get_best_audio(&video.id)
+            .await
+            .map_err(|err| anyhow!("{err}"))?;
+        Ok(Audio {
+            expires_at: audio
+                .expire()
+                .map(|expire| UNIX_EPOCH + Duration::from_secs(expire)),
+            uri: audio.url,
+            loudness: audio
+                .loudness_db
+                .map(|db| YOUTUBE_REFERENCE_LUFS + f64::from(db)),
+            itag: Some(audio.itag),
+        })
+    }
+}
+
+/// `file://` uri of a file on the server, with the loudness of its track in the library
+pub struct LocalFileResolver {
+    pub conn: DatabaseConnection,
+}
+
+#[axum::async_trait]
+impl AudioResolver for LocalFileResolver {
+    fn handles(&self, video: &Video) -> bool {
+        video.id.starts_with("file://")
+    }
+
+    async fn resolve(&self, video: &Video) -> Result<Audio> {
+        let (path, _) = glib::filename_from_uri(&video.id)?;
+        if !tokio::fs::try_exists(&path).await? {
+            bail!("{} does not exist", path.display());
+        }
+        Ok(Audio {
+            loudness: sql::track::find(&self.conn, &video.id)
+                .await?
+                .and_then(|track| track.loudness_lufs),
+            ..Audio::new(video.id.clone())
+        })
+    }
+}
+
+/// Plain HTTP stream of one of the allowed `hosts`, played as is
+pub struct HttpResolver {
+    pub hosts: Vec<String>,
+}
+
+#[axum::async_trait]
+impl AudioResolver for HttpResolver {
+    fn handles(&self, video: &Video) -> bool {
+        reqwest::Url::parse(&video.id).is_ok_and(|url| {
+            matches!(url.scheme(), "http" | "https")
+                && url.host_str().is_some_and(|host| {
+                    self.hosts
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+                })
+        })
+    }
+
+    async fn resolve(&self, video: &Video) -> Result<Audio> {
+        Ok(Audio::new(video.id.clone()))
+    }
+}
+
+/// Sine wave described by `tone:<frequency in Hz>[:<duration in seconds>]`, to test the player offline,
+/// its WAV file is written once in `dir`
+pub struct ToneResolver {
+    pub dir: PathBuf,
+}
+
+#[axum::async_trait]
+impl AudioResolver for ToneResolver {
+    fn handles(&self, video: &Video) -> bool {
+        video.id.starts_with("tone:")
+    }
+
+    async fn resolve(&self, video: &Video) -> Result<Audio> {
+        let mut params = video.id.trim_start_matches("tone:").split(':');
+        let frequency: u32 = params
+            .next()
+            .and_then(|f| f.parse().ok())
+            .filter(|f| (1..=TONE_MAX_FREQUENCY).contains(f))
+            .ok_or_else(|| anyhow!("Invalid tone frequency: {}", video.id))?;
+        let seconds: u32 = match params.next() {
+            Some(s) => s
+                .parse()
+                .map_err(|_| anyhow!("Invalid tone duration: {}", video.id))?,
+            None => TONE_DEFAULT_SECONDS,
+        };
+        if seconds > TONE_MAX_SECONDS {
+            bail!("Tones last at most {TONE_MAX_SECONDS} seconds");
+        }
+
+        let path = self.dir.join(format!("tone_{frequency}_{seconds}.wav"));
+        if !tokio::fs::try_exists(&path).await? {
+            // Written under a name of its own then renamed, so that a file being written is never played
+            static WRITES: AtomicU64 = AtomicU64::new(0);
+            let part = self.dir.join(format!(
+                "tone_{frequency}_{seconds}.wav.{}.{}.part",
+                std::process::id(),
+                WRITES.fetch_add(1, Ordering::Relaxed)
+            ));
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(&part, sine_wav(frequency, seconds)).await?;
+            if let Err(err) = tokio::fs::rename(&part, &path).await {
+                let _ = tokio::fs::remove_file(&part).await;
+                return Err(err.into());
+            }
+        }
+        // `playbin` needs an absolute uri and the cache directory may be relative
+        let path = tokio::fs::canonicalize(&path).await?;
+        Ok(Audio::new(glib::filename_to_uri(&path, None)?.to_string()))
+    }
+}
+
+/// Mono 16 bits PCM WAV file of a sine wave
+fn sine_wav(frequency: u32, seconds: u32) -> Vec<u8> {
+    let samples = TONE_SAMPLE_RATE * seconds;
+    let data_size = samples * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // Size of the fmt chunk
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Channels
+    wav.extend_from_slice(&TONE_SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(TONE_SAMPLE_RATE * 2).to_le_bytes()); // Bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // Bytes per sample
+    wav.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for i in 0..samples {
+        let t = i as f64 / TONE_SAMPLE_RATE as f64;
+        let sample = ((2.0 * PI * frequency as f64 * t).sin() * i16::MAX as f64 * 0.5) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autoplay::AutoplayStrategy;
+    use crate::music_player::sink::OutputSink;
+
+    fn video(id: &str) -> Video {
+        Video {
+            id: id.to_owned(),
+            title: String::new(),
+            thumbnail: String::new(),
+            author: String::new(),
+            duration: String::new(),
+        }
+    }
+
+    fn config(http_hosts: &[&str], test_tones: bool) -> Config {
+        Config {
+            library_dirs: vec![],
+            output: OutputSink::default(),
+            admins: vec![],
+            autoplay: AutoplayStrategy::default(),
+            cache_dir: std::env::temp_dir(),
+            cache_size: 0,
+            http_hosts: http_hosts.iter().map(|host| host.to_string()).collect(),
+            test_tones,
+        }
+    }
+
+    #[test]
+    fn http_handles() {
+        let resolver = HttpResolver {
+            hosts: vec!["radio.example.com".to_owned()],
+        };
+        let cases = [
+            ("http://radio.example.com/stream.mp3", true),
+            ("https://radio.example.com/stream.mp3", true),
+            ("https://RADIO.Example.com/stream.mp3", true),
+            ("https://example.com/stream.mp3", false),
+            ("https://radio.example.com.evil.org/stream.mp3", false),
+            ("ftp://radio.example.com/stream.mp3", false),
+            ("file:///radio.example.com/stream.mp3", false),
+            ("dQw4w9WgXcQ", false),
+        ];
+        for (id, expected) in cases {
+            assert_eq!(resolver.handles(&video(id)), expected, "{id:?}");
+        }
+    }
+
+    #[test]
+    fn youtube_and_local_file_handle() {
+        let local_file = LocalFileResolver {
+            conn: DatabaseConnection::Disconnected,
+        };
+        // Whether the YouTube and the local file resolvers handle the id
+        let cases = [
+            ("dQw4w9WgXcQ", (true, false)),
+            ("a-b_c", (true, false)),
+            ("", (false, false)),
+            ("file:///music/song.flac", (false, true)),
+            ("tone:440", (false, false)),
+            ("tone:440:5", (false, false)),
+            ("https://example.com/stream.mp3", (false, false)),
+        ];
+        for (id, expected) in cases {
+            let handled = (
+                YoutubeResolver.handles(&video(id)),
+                local_file.handles(&video(id)),
+            );
+            assert_eq!(handled, expected, "{id:?}");
+        }
+    }
+
+    #[test]
+    fn from_config_order() {
+        let ids = [
+            "file:///music/song.flac",
+            "https://radio.example.com/stream.mp3",
+            "tone:440",
+            "dQw4w9WgXcQ",
+        ];
+        // Index of the resolver handling each id first
+        let cases = [
+            (config(&[], false), [Some(0), None, None, Some(1)]),
+            (
+                config(&["radio.example.com"], false),
+                [Some(0), Some(1), None, Some(2)],
+            ),
+            (config(&[], true), [Some(0), None, Some(1), Some(2)]),
+            (
+                config(&["radio.example.com"], true),
+                [Some(0), Some(1), Some(2), Some(3)],
+            ),
+        ];
+        for (config, expected) in cases {
+            let resolvers = Resolvers::from_config(&config, &DatabaseConnection::Disconnected);
+            let first = ids.map(|id| resolvers.0.iter().position(|r| r.handles(&video(id))));
+            assert_eq!(
+                first, expected,
+                "{:?} {}",
+                config.http_hosts, config.test_tones
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn tone_resolve() {
+        let resolver = ToneResolver {
+            dir: std::env::temp_dir().join("jukebox_tones"),
+        };
+        let audio = resolver.resolve(&video("tone:440:1")).await.unwrap();
+        let (path, _) = glib::filename_from_uri(&audio.uri).unwrap();
+        let wav = std::fs::read(path).unwrap();
+        assert_eq!(wav, sine_wav(440, 1));
+        assert_eq!(audio.loudness, None);
+        assert_eq!(audio.expires_at, None);
+
+        let invalid = [
+            "tone:",
+            "tone:0",
+            "tone:20001",
+            "tone:abc",
+            "tone:440:601",
+            "tone:440:abc",
+            "tone:440:-1",
+        ];
+        for id in invalid {
+            assert!(resolver.resolve(&video(id)).await.is_err(), "{id:?}");
+        }
+    }
+}
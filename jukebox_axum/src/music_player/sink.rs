@@ -0,0 +1,110 @@
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Where the mixed musics are played
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutputSink {
+    #[default]
+    Auto,
+    /// ALSA device, like `hw:1`
+    Alsa { device: Option<String> },
+    /// Name of the PulseAudio sink
+    Pulse { device: Option<String> },
+    /// WAV file
+    File { path: PathBuf },
+    /// Nothing is heard, for headless servers and tests
+    Fake,
+}
+
+impl OutputSink {
+    /// End of the output pipeline description playing the musics
+    pub fn description(&self) -> String {
+        match self {
+            OutputSink::Auto => "autoaudiosink".to_owned(),
+            OutputSink::Alsa { device: None } => "alsasink".to_owned(),
+            OutputSink::Alsa {
+                device: Some(device),
+            } => format!("alsasink device={}", quote(device)),
+            OutputSink::Pulse { device: None } => "pulsesink".to_owned(),
+            OutputSink::Pulse {
+                device: Some(device),
+            } => format!("pulsesink device={}", quote(device)),
+            OutputSink::File { path } => format!(
+                "wavenc ! filesink location={}",
+                quote(&path.to_string_lossy())
+            ),
+            // Synchronized so that the musics last as long as when they are heard
+            OutputSink::Fake => "fakesink sync=true".to_owned(),
+        }
+    }
+}
+
+/// Parse the sink given in the configuration: `auto`, `alsa[:<device>]`, `pulse[:<sink>]`, `file:<path>` or `fakesink`
+impl FromStr for OutputSink {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, device) = match s.split_once(':') {
+            Some((kind, device)) => (kind, Some(device.to_owned())),
+            None => (s, None),
+        };
+        Ok(match (kind, device) {
+            ("auto", None) => OutputSink::Auto,
+            ("alsa", device) => OutputSink::Alsa { device },
+            ("pulse", device) => OutputSink::Pulse { device },
+            ("file", Some(path)) if !path.is_empty() => OutputSink::File { path: path.into() },
+            ("fakesink" | "fake", None) => OutputSink::Fake,
+            _ => bail!("Unknown output sink: {s}"),
+        })
+    }
+}
+
+/// Quote a property value of a pipeline description
+pub(crate) fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sink() {
+        let cases = [
+            ("auto", Some(OutputSink::Auto)),
+            ("alsa", Some(OutputSink::Alsa { device: None })),
+            (
+                "alsa:hw:1",
+                Some(OutputSink::Alsa {
+                    device: Some("hw:1".to_owned()),
+                }),
+            ),
+            ("pulse", Some(OutputSink::Pulse { device: None })),
+            (
+                "pulse:speakers",
+                Some(OutputSink::Pulse {
+                    device: Some("speakers".to_owned()),
+                }),
+            ),
+            (
+                "file:/tmp/out.wav",
+                Some(OutputSink::File {
+                    path: "/tmp/out.wav".into(),
+                }),
+            ),
+            ("fakesink", Some(OutputSink::Fake)),
+            ("fake", Some(OutputSink::Fake)),
+            ("file", None),
+            ("file:", None),
+            ("auto:hw:1", None),
+            ("jack", None),
+            ("", None),
+        ];
+        for (sink, expected) in cases {
+            assert_eq!(sink.parse().ok(), expected, "{sink:?}");
+        }
+    }
+}
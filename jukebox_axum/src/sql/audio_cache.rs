@@ -0,0 +1,67 @@
+use entity::audio_cache::{ActiveModel, Column, Entity, Model};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, Set};
+
+/// File of the cache holding the audio of a video, the last one played when there are several formats
+pub async fn find(conn: &DatabaseConnection, video_id: &str) -> Result<Option<Model>, DbErr> {
+    Entity::find()
+        .filter(Column::VideoId.eq(video_id))
+        .order_by_desc(Column::PlayedAt)
+        .one(conn)
+        .await
+}
+
+/// Insert or replace a file downloaded in the cache
+pub async fn save(conn: &DatabaseConnection, file: Model) -> Result<(), DbErr> {
+    Entity::insert(ActiveModel {
+        video_id: Set(file.video_id),
+        itag: Set(file.itag),
+        file_name: Set(file.file_name),
+        size: Set(file.size),
+        loudness_db: Set(file.loudness_db),
+        played_at: Set(file.played_at),
+    })
+    .on_conflict(
+        OnConflict::columns([Column::VideoId, Column::Itag])
+            .update_columns([
+                Column::FileName,
+                Column::Size,
+                Column::LoudnessDb,
+                Column::PlayedAt,
+            ])
+            .to_owned(),
+    )
+    .exec(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_played(
+    conn: &DatabaseConnection,
+    video_id: &str,
+    itag: u32,
+    played_at: i64,
+) -> Result<(), DbErr> {
+    Entity::update_many()
+        .col_expr(Column::PlayedAt, played_at.into())
+        .filter(Column::VideoId.eq(video_id))
+        .filter(Column::Itag.eq(itag))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn remove(conn: &DatabaseConnection, video_id: &str, itag: u32) -> Result<(), DbErr> {
+    Entity::delete_by_id((video_id.to_owned(), itag))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+/// Every file of the cache, the least recently played first
+pub async fn least_recently_played(conn: &DatabaseConnection) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .order_by_asc(Column::PlayedAt)
+        .all(conn)
+        .await
+}
@@ -0,0 +1,94 @@
+use entity::{history, video};
+use jukebox_rust::{HistoryEntry, PlayOutcome, QueueEntry};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, Order, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set,
+};
+
+/// Musics of the history to return, `None` matches every music
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub user: Option<String>,   // User who queued the music
+    pub search: Option<String>, // Part of the title
+    pub outcome: Option<PlayOutcome>,
+}
+
+/// Save a music played by the jukebox
+pub async fn record(
+    conn: &DatabaseConnection,
+    entry: &QueueEntry,
+    started_at: i64,
+    ended_at: i64,
+    outcome: PlayOutcome,
+) -> Result<(), DbErr> {
+    history::Entity::insert(history::ActiveModel {
+        video_id: Set(entry.video.id.to_owned()),
+        added_by: Set(entry.added_by.to_owned()),
+        auto_added: Set(entry.auto_added),
+        started_at: Set(started_at),
+        ended_at: Set(ended_at),
+        outcome: Set(outcome.as_str().to_owned()),
+        ..Default::default()
+    })
+    .exec(conn)
+    .await?;
+    Ok(())
+}
+
+/// Musics matching `filter`, the most recent first, with the number of matching musics
+pub async fn page(
+    conn: &DatabaseConnection,
+    filter: &Filter,
+    page: u64,
+    per_page: u64,
+) -> Result<(Vec<HistoryEntry>, u64), DbErr> {
+    let mut query = history::Entity::find().find_also_related(video::Entity);
+    if let Some(user) = &filter.user {
+        query = query.filter(history::Column::AddedBy.eq(user.as_str()));
+    }
+    if let Some(search) = &filter.search {
+        query = query.filter(video::Column::Title.contains(search));
+    }
+    if let Some(outcome) = filter.outcome {
+        query = query.filter(history::Column::Outcome.eq(outcome.as_str()));
+    }
+    let total = query.clone().count(conn).await? as u64;
+    let entries = query
+        .order_by_desc(history::Column::StartedAt)
+        .order_by_desc(history::Column::Id)
+        .offset(page * per_page)
+        .limit(per_page)
+        .all(conn)
+        .await?
+        .into_iter()
+        .filter_map(|(played, video)| {
+            Some(HistoryEntry {
+                id: played.id,
+                video: video?,
+                added_by: played.added_by,
+                auto_added: played.auto_added,
+                started_at: played.started_at,
+                ended_at: played.ended_at,
+                outcome: PlayOutcome::parse(&played.outcome)?,
+            })
+        })
+        .collect();
+    Ok((entries, total))
+}
+
+/// Random video among the ones played until their end, except the `excluded` ones,
+/// the videos played more often are more likely to be picked
+pub async fn random_video(
+    conn: &DatabaseConnection,
+    excluded: &[String],
+) -> Result<Option<video::Model>, DbErr> {
+    Ok(history::Entity::find()
+        .filter(history::Column::Outcome.eq(PlayOutcome::Finished.as_str()))
+        .filter(history::Column::VideoId.is_not_in(excluded.iter().cloned()))
+        .order_by(Expr::cust("RANDOM()"), Order::Asc)
+        .find_also_related(video::Entity)
+        .one(conn)
+        .await?
+        .and_then(|(_, video)| video))
+}
@@ -1 +1,6 @@
+pub mod audio_cache;
+pub mod history;
+pub mod queue;
+pub mod setting;
+pub mod track;
 pub mod user;
@@ -0,0 +1,295 @@
+use entity::{queue, video};
+use jukebox_rust::{EntryId, QueueEntry};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+};
+use std::time::UNIX_EPOCH;
+
+/// Get the entries of the queue in the order they will be played
+pub async fn load_queue(conn: &DatabaseConnection) -> Result<Vec<QueueEntry>, DbErr> {
+    Ok(queue::Entity::find()
+        .order_by_asc(queue::Column::Position)
+        .find_also_related(video::Entity)
+        .all(conn)
+        .await?
+        .into_iter()
+        .filter_map(|(entry, video)| {
+            video.map(|video| QueueEntry {
+                id: entry.id,
+                video,
+                added_by: entry.added_by,
+                added_at: entry.added_at,
+                auto_added: entry.auto_added,
+                rate: entry.rate,
+            })
+        })
+        .collect())
+}
+
+/// Add a video at the `position` of the queue (should be the end of the queue)
+pub async fn push(
+    conn: &DatabaseConnection,
+    position: usize,
+    video: &video::Model,
+    added_by: &str,
+    auto_added: bool,
+) -> Result<QueueEntry, DbErr> {
+    let added_at = std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+
+    let txn = conn.begin().await?;
+    video::Entity::insert(video::ActiveModel {
+        id: Set(video.id.to_owned()),
+        title: Set(video.title.to_owned()),
+        thumbnail: Set(video.thumbnail.to_owned()),
+        author: Set(video.author.to_owned()),
+        duration: Set(video.duration.to_owned()),
+    })
+    .on_conflict(
+        OnConflict::column(video::Column::Id)
+            .update_columns([
+                video::Column::Title,
+                video::Column::Thumbnail,
+                video::Column::Author,
+                video::Column::Duration,
+            ])
+            .to_owned(),
+    )
+    .exec(&txn)
+    .await?;
+    let id = queue::Entity::insert(queue::ActiveModel {
+        position: Set(position as i32),
+        video_id: Set(video.id.to_owned()),
+        added_by: Set(added_by.to_owned()),
+        added_at: Set(added_at),
+        auto_added: Set(auto_added),
+        ..Default::default()
+    })
+    .exec(&txn)
+    .await?
+    .last_insert_id;
+    txn.commit().await?;
+
+    Ok(QueueEntry {
+        id,
+        video: video.clone(),
+        added_by: added_by.to_owned(),
+        added_at,
+        auto_added,
+        rate: None,
+    })
+}
+
+/// Set the playback rate of the entry `id`, None to use the global one
+pub async fn set_rate(
+    conn: &DatabaseConnection,
+    id: EntryId,
+    rate: Option<f64>,
+) -> Result<(), DbErr> {
+    queue::Entity::update_many()
+        .col_expr(queue::Column::Rate, Expr::value(rate))
+        .filter(queue::Column::Id.eq(id))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+/// Remove the entry `id` and shift the following ones
+pub async fn remove(conn: &DatabaseConnection, id: EntryId) -> Result<(), DbErr> {
+    let txn = conn.begin().await?;
+    if let Some(entry) = queue::Entity::find_by_id(id).one(&txn).await? {
+        queue::Entity::delete_by_id(id).exec(&txn).await?;
+        queue::Entity::update_many()
+            .col_expr(
+                queue::Column::Position,
+                Expr::col(queue::Column::Position).sub(1),
+            )
+            .filter(queue::Column::Position.gt(entry.position))
+            .exec(&txn)
+            .await?;
+    }
+    txn.commit().await
+}
+
+/// Move the entry `id` to `position` and shift the entries in between,
+/// a position past the end of the queue moves it to the end
+pub async fn move_to(conn: &DatabaseConnection, id: EntryId, position: i32) -> Result<(), DbErr> {
+    let txn = conn.begin().await?;
+    let last = queue::Entity::find()
+        .order_by_desc(queue::Column::Position)
+        .one(&txn)
+        .await?;
+    if let (Some(entry), Some(last)) = (queue::Entity::find_by_id(id).one(&txn).await?, last) {
+        let position = position.clamp(0, last.position);
+        let (shift, low, high) = if position > entry.position {
+            (-1, entry.position + 1, position)
+        } else {
+            (1, position, entry.position - 1)
+        };
+        queue::Entity::update_many()
+            .col_expr(
+                queue::Column::Position,
+                Expr::col(queue::Column::Position).add(shift),
+            )
+            .filter(queue::Column::Position.between(low, high))
+            .exec(&txn)
+            .await?;
+        queue::Entity::update_many()
+            .col_expr(queue::Column::Position, Expr::value(position))
+            .filter(queue::Column::Id.eq(id))
+            .exec(&txn)
+            .await?;
+    }
+    txn.commit().await
+}
+
+/// Give the entries `ids` the positions of their indexes, when the whole queue is reordered
+pub async fn set_order(conn: &DatabaseConnection, ids: &[EntryId]) -> Result<(), DbErr> {
+    let txn = conn.begin().await?;
+    for (position, id) in ids.iter().enumerate() {
+        queue::Entity::update_many()
+            .col_expr(queue::Column::Position, Expr::value(position as i32))
+            .filter(queue::Column::Id.eq(*id))
+            .exec(&txn)
+            .await?;
+    }
+    txn.commit().await
+}
+
+/// Swap the positions of the entries `id1` and `id2`
+pub async fn swap(conn: &DatabaseConnection, id1: EntryId, id2: EntryId) -> Result<(), DbErr> {
+    let txn = conn.begin().await?;
+    let entry1 = queue::Entity::find_by_id(id1).one(&txn).await?;
+    let entry2 = queue::Entity::find_by_id(id2).one(&txn).await?;
+    if let (Some(entry1), Some(entry2)) = (entry1, entry2) {
+        for (id, position) in [(id1, entry2.position), (id2, entry1.position)] {
+            queue::Entity::update_many()
+                .col_expr(queue::Column::Position, Expr::value(position))
+                .filter(queue::Column::Id.eq(id))
+                .exec(&txn)
+                .await?;
+        }
+    }
+    txn.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{ConnectOptions, Database};
+
+    /// Database with the schema of the server, each connection to `sqlite::memory:` opens a new one
+    async fn connect() -> DatabaseConnection {
+        let mut options = ConnectOptions::new("sqlite::memory:".to_owned());
+        options.max_connections(1);
+        let conn = Database::connect(options).await.unwrap();
+        crate::setup_schema(&conn).await;
+        conn
+    }
+
+    fn video(id: &str) -> video::Model {
+        video::Model {
+            id: id.to_owned(),
+            title: format!("Title of {id}"),
+            thumbnail: String::new(),
+            author: "Author".to_owned(),
+            duration: "3:00".to_owned(),
+        }
+    }
+
+    /// Queue the videos `ids` in order, returns the ids of their entries
+    async fn fill(conn: &DatabaseConnection, ids: &[&str]) -> Vec<EntryId> {
+        let mut entries = vec![];
+        for (position, id) in ids.iter().enumerate() {
+            let entry = push(conn, position, &video(id), "user", false).await;
+            entries.push(entry.unwrap().id);
+        }
+        entries
+    }
+
+    /// Ids of the videos of the saved queue, in order
+    async fn saved(conn: &DatabaseConnection) -> Vec<String> {
+        load_queue(conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.video.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn push_and_load() {
+        let conn = connect().await;
+        fill(&conn, &["a", "b"]).await;
+        let entry = push(&conn, 2, &video("c"), "other", true).await.unwrap();
+
+        let queue = load_queue(&conn).await.unwrap();
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue[2], entry);
+        assert_eq!(queue[2].added_by, "other");
+        assert!(queue[2].auto_added);
+        assert_eq!(saved(&conn).await, ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn remove_from_the_middle() {
+        let conn = connect().await;
+        let entries = fill(&conn, &["a", "b", "c", "d"]).await;
+        remove(&conn, entries[1]).await.unwrap();
+        assert_eq!(saved(&conn).await, ["a", "c", "d"]);
+
+        // The positions were shifted, so the next video is queued at the end
+        push(&conn, 3, &video("e"), "user", false).await.unwrap();
+        assert_eq!(saved(&conn).await, ["a", "c", "d", "e"]);
+    }
+
+    #[tokio::test]
+    async fn move_up() {
+        let conn = connect().await;
+        let entries = fill(&conn, &["a", "b", "c", "d"]).await;
+        move_to(&conn, entries[3], 1).await.unwrap();
+        assert_eq!(saved(&conn).await, ["a", "d", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn move_down() {
+        let conn = connect().await;
+        let entries = fill(&conn, &["a", "b", "c", "d"]).await;
+        move_to(&conn, entries[0], 2).await.unwrap();
+        assert_eq!(saved(&conn).await, ["b", "c", "a", "d"]);
+    }
+
+    #[tokio::test]
+    async fn move_to_the_same_index() {
+        let conn = connect().await;
+        let entries = fill(&conn, &["a", "b", "c"]).await;
+        move_to(&conn, entries[1], 1).await.unwrap();
+        assert_eq!(saved(&conn).await, ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn move_out_of_range() {
+        let conn = connect().await;
+        let entries = fill(&conn, &["a", "b", "c", "d"]).await;
+        move_to(&conn, entries[1], 10).await.unwrap();
+        assert_eq!(saved(&conn).await, ["a", "c", "d", "b"]);
+
+        // No position was skipped, so the next video is queued after the moved one
+        push(&conn, 4, &video("e"), "user", false).await.unwrap();
+        assert_eq!(saved(&conn).await, ["a", "c", "d", "b", "e"]);
+    }
+
+    #[tokio::test]
+    async fn reorder() {
+        let conn = connect().await;
+        let entries = fill(&conn, &["a", "b", "c", "d"]).await;
+        set_order(&conn, &[entries[0], entries[3], entries[1], entries[2]])
+            .await
+            .unwrap();
+        assert_eq!(saved(&conn).await, ["a", "d", "b", "c"]);
+    }
+}
@@ -0,0 +1,38 @@
+use entity::setting::{ActiveModel, Column, Entity};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, Set};
+
+pub const PLAYBACK_POSITION: &str = "playback_position_ms";
+pub const VOLUME: &str = "volume";
+pub const CROSSFADE: &str = "crossfade_ms";
+pub const FADE: &str = "fade_ms";
+pub const NORMALIZATION_ENABLED: &str = "normalization_enabled";
+pub const NORMALIZATION_TARGET: &str = "normalization_target_lufs";
+pub const REPEAT: &str = "repeat"; // off, one or all
+pub const SHUFFLE: &str = "shuffle";
+pub const EQUALIZER_PRESET: &str = "equalizer_preset";
+pub const PLAYBACK_RATE: &str = "playback_rate";
+pub const EQUALIZER_GAINS: &str = "equalizer_gains_db"; // Gains of the bands separated by commas
+pub const OUTPUT: &str = "output_sink"; // Sink chosen by an administrator, in JSON
+
+pub async fn get(conn: &DatabaseConnection, key: &str) -> Result<Option<String>, DbErr> {
+    Ok(Entity::find_by_id(key.to_owned())
+        .one(conn)
+        .await?
+        .map(|setting| setting.value))
+}
+
+pub async fn set(conn: &DatabaseConnection, key: &str, value: String) -> Result<(), DbErr> {
+    Entity::insert(ActiveModel {
+        key: Set(key.to_owned()),
+        value: Set(value),
+    })
+    .on_conflict(
+        OnConflict::column(Column::Key)
+            .update_column(Column::Value)
+            .to_owned(),
+    )
+    .exec(conn)
+    .await?;
+    Ok(())
+}
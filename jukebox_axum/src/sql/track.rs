@@ -0,0 +1,111 @@
+use entity::track::{ActiveModel, Column, Entity, Model};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use std::collections::HashMap;
+
+/// Insert or update a track found by a scan of the library
+pub async fn save(conn: &DatabaseConnection, track: Model) -> Result<(), DbErr> {
+    Entity::insert(ActiveModel {
+        uri: Set(track.uri),
+        title: Set(track.title),
+        artist: Set(track.artist),
+        album: Set(track.album),
+        duration_ms: Set(track.duration_ms),
+        scanned_at: Set(track.scanned_at),
+        loudness_lufs: Set(track.loudness_lufs),
+    })
+    .on_conflict(
+        OnConflict::column(Column::Uri)
+            .update_columns([
+                Column::Title,
+                Column::Artist,
+                Column::Album,
+                Column::DurationMs,
+                Column::ScannedAt,
+                Column::LoudnessLufs,
+            ])
+            .to_owned(),
+    )
+    .exec(conn)
+    .await?;
+    Ok(())
+}
+
+/// Remove the tracks that were not found by the scan started at `scanned_at`
+pub async fn remove_missing(conn: &DatabaseConnection, scanned_at: i64) -> Result<u64, DbErr> {
+    Ok(Entity::delete_many()
+        .filter(Column::ScannedAt.lt(scanned_at))
+        .exec(conn)
+        .await?
+        .rows_affected)
+}
+
+/// Tracks whose title, artist or album contains `search`
+pub async fn search(
+    conn: &DatabaseConnection,
+    search: &str,
+    limit: u64,
+) -> Result<Vec<Model>, DbErr> {
+    Entity::find()
+        .filter(
+            Condition::any()
+                .add(Column::Title.contains(search))
+                .add(Column::Artist.contains(search))
+                .add(Column::Album.contains(search)),
+        )
+        .order_by_asc(Column::Artist)
+        .order_by_asc(Column::Album)
+        .order_by_asc(Column::Title)
+        .limit(limit)
+        .all(conn)
+        .await
+}
+
+/// Loudness of the tracks known by a previous scan, so that a scan does not measure them again
+pub async fn loudness(conn: &DatabaseConnection) -> Result<HashMap<String, f64>, DbErr> {
+    Ok(Entity::find()
+        .filter(Column::LoudnessLufs.is_not_null())
+        .all(conn)
+        .await?
+        .into_iter()
+        .filter_map(|track| Some((track.uri, track.loudness_lufs?)))
+        .collect())
+}
+
+/// Uris of the tracks whose loudness is not known yet
+pub async fn unmeasured(conn: &DatabaseConnection) -> Result<Vec<String>, DbErr> {
+    Entity::find()
+        .select_only()
+        .column(Column::Uri)
+        .filter(Column::LoudnessLufs.is_null())
+        .into_tuple()
+        .all(conn)
+        .await
+}
+
+pub async fn set_loudness(
+    conn: &DatabaseConnection,
+    uri: &str,
+    loudness_lufs: f64,
+) -> Result<(), DbErr> {
+    Entity::update_many()
+        .col_expr(Column::LoudnessLufs, Expr::value(loudness_lufs))
+        .filter(Column::Uri.eq(uri))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn find(conn: &DatabaseConnection, uri: &str) -> Result<Option<Model>, DbErr> {
+    Entity::find_by_id(uri.to_owned()).one(conn).await
+}
+
+pub async fn exists(conn: &DatabaseConnection, uri: &str) -> Result<bool, DbErr> {
+    Ok(Entity::find_by_id(uri.to_owned())
+        .one(conn)
+        .await?
+        .is_some())
+}
@@ -0,0 +1,113 @@
+use crate::login::jwt_token::AuthToken;
+use crate::AppState;
+use axum::body::{Bytes, StreamBody};
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use futures::StreamExt;
+use gstreamer::prelude::Cast;
+use gstreamer::{BufferFlags, Element, FlowError, FlowSuccess};
+use gstreamer_app::{AppSink, AppSinkCallbacks};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::log;
+
+/// Pages buffered for each listener, a listener too slow to read them skips the oldest ones
+const STREAM_CAPACITY: usize = 256;
+
+/// Encoded output of the music player, sent to every listener of `/stream`
+pub struct AudioStream {
+    tx: broadcast::Sender<Bytes>,
+    headers: Mutex<Headers>,
+}
+
+/// Header pages of the Ogg stream, sent first to the listeners joining while it is playing
+#[derive(Default)]
+struct Headers {
+    pages: Vec<Bytes>,
+    complete: bool, // The data pages started, new header pages belong to a new stream
+}
+
+impl Default for AudioStream {
+    fn default() -> Self {
+        AudioStream {
+            tx: broadcast::channel(STREAM_CAPACITY).0,
+            headers: Mutex::new(Headers::default()),
+        }
+    }
+}
+
+impl AudioStream {
+    /// Read the pages encoded by `appsink`, called each time the output pipeline is built
+    pub fn connect(self: &Arc<Self>, appsink: Element) {
+        let Ok(appsink) = appsink.downcast::<AppSink>() else {
+            log::error!("The stream sink is not an appsink");
+            return;
+        };
+        let stream = self.clone();
+        appsink.set_callbacks(
+            AppSinkCallbacks::builder()
+                .new_sample(move |appsink| {
+                    let sample = appsink.pull_sample().map_err(|_| FlowError::Eos)?;
+                    let buffer = sample.buffer().ok_or(FlowError::Error)?;
+                    let map = buffer.map_readable().map_err(|_| FlowError::Error)?;
+                    stream.push(
+                        Bytes::copy_from_slice(&map),
+                        buffer.flags().contains(BufferFlags::HEADER),
+                    );
+                    Ok(FlowSuccess::Ok)
+                })
+                .build(),
+        );
+    }
+
+    fn push(&self, page: Bytes, header: bool) {
+        let mut headers = self.headers.lock().unwrap();
+        if header {
+            if headers.complete {
+                *headers = Headers::default();
+            }
+            headers.pages.push(page.clone());
+        } else {
+            headers.complete = true;
+        }
+        // Nobody may be listening
+        let _ = self.tx.send(page);
+    }
+
+    /// Header pages of the stream and the pages following them
+    fn subscribe(&self) -> (Vec<Bytes>, broadcast::Receiver<Bytes>) {
+        let headers = self.headers.lock().unwrap();
+        (headers.pages.clone(), self.tx.subscribe())
+    }
+}
+
+/// Continuous Ogg/Opus stream of what the jukebox is playing
+#[axum::debug_handler]
+pub async fn stream_handler(
+    State(state): State<Arc<AppState>>,
+    token: AuthToken,
+) -> impl IntoResponse {
+    log::info!("`{}` listens to the stream", token.username);
+    let (headers, rx) = state.stream.subscribe();
+    let pages = futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(page) => return Some((Ok::<_, Infallible>(page), rx)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("A listener of the stream skipped {skipped} pages");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    let body = futures::stream::iter(headers.into_iter().map(Ok)).chain(pages);
+    (
+        [
+            (header::CONTENT_TYPE, "audio/ogg"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        StreamBody::new(body),
+    )
+}
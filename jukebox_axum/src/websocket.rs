@@ -1,123 +1,95 @@
-use crate::music_player::MusicPlayerMessage;
-use crate::AppState;
+use crate::login::jwt_token::AuthToken;
+use crate::music_player::{random_below, MusicPlayerMessage, PlayerStatus};
+use crate::{library, sql, AppState};
 use anyhow::Result;
-use axum::extract::ws::{self, Message, WebSocket};
+use axum::extract::ws::{self, close_code, CloseFrame, Message, WebSocket};
 use axum::extract::{State, WebSocketUpgrade};
 use axum::response::IntoResponse;
 use entity::video::Model as Video;
-use futures::stream::SplitSink;
+use futures::stream::{SplitSink, SplitStream};
 use futures::{sink::SinkExt, stream::StreamExt};
-use jukebox_rust::NetData;
+use jukebox_rust::{
+    capability, ClientRequest, EntryId, Equalizer, EqualizerPreset, ErrorKind, NetData,
+    Normalization, QueueEntry, Transitions, CAPABILITIES, EQUALIZER_BANDS, MAX_PLAYBACK_RATE,
+    MIN_PLAYBACK_RATE, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use my_youtube_extractor::youtube_info::YtVideoPageInfo;
+use sea_orm::DbErr;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tracing::log;
 
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const LIBRARY_SEARCH_LIMIT: u64 = 20;
+
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
+    token: AuthToken,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| websocket(socket, state))
+    ws.on_upgrade(|socket| websocket(socket, state, token.username))
 }
 
-async fn websocket(stream: WebSocket, state: Arc<AppState>) {
+async fn websocket(stream: WebSocket, state: Arc<AppState>, username: String) {
     let (mut sender, mut receiver) = stream.split();
 
+    let capabilities = match handshake(&mut sender, &mut receiver).await {
+        Ok(capabilities) => capabilities,
+        Err(reason) => {
+            log::warn!("Closing websocket: {reason}");
+            let _ = sender
+                .send(Message::Close(Some(CloseFrame {
+                    code: close_code::PROTOCOL,
+                    reason: reason.into(),
+                })))
+                .await;
+            return;
+        }
+    };
+
     let mut rx = state.tx.subscribe();
     let (tx_single, mut rx_single) = mpsc::channel(1000);
+    let snapshot_supported = capabilities.iter().any(|c| c == capability::SNAPSHOT);
+    if snapshot_supported {
+        if let Err(err) = send_snapshot(&state, &mut sender, &mut rx, &capabilities).await {
+            log::error!("Error sending snapshot: {err}");
+            return;
+        }
+    }
+    let state_broadcast = state.clone();
 
     let mut recv_user_task = tokio::spawn(async move {
         while let Some(Ok(msg)) = receiver.next().await {
             log::debug!("Received a message !");
             match msg {
                 Message::Binary(data) => match NetData::decode_message(data.as_slice()) {
-                    Ok(msg) => match msg {
-                        NetData::Remove(index, video_id) => {
-                            log::debug!("Removing video: {}", video_id);
-                            let mut playlist = state.list.lock().await;
-                            if let Some(video) = playlist.get(index) && video.id == video_id {
-                                playlist.remove(index);
-                                state.music_player_tx.send(MusicPlayerMessage::RemoveVideo(index, video_id.clone())).unwrap();
-                                state.tx.send(NetData::Remove(index, video_id)).unwrap();
-                            }
-                            else {
-                                log::error!("Trying to remove a video that is not in the playlist");
-                            }
-                        }
-                        NetData::Add(video) => {
-                            log::debug!("Adding video: {}", video.title);
-                            let mut playlist = state.list.lock().await;
-                            playlist.push(video.clone());
-                            state
-                                .music_player_tx
-                                .send(MusicPlayerMessage::AddMusic(video.clone()))
-                                .unwrap();
-                            state.tx.send(NetData::Add(video)).unwrap();
-                        }
-                        NetData::Search(search_txt) => {
-                            log::debug!("Search videos: {search_txt}");
-                            match my_youtube_extractor::search_videos(&search_txt).await {
-                                Err(why) => log::error!("Error searching videos : {}", why),
-                                Ok(videos) => tx_single
-                                    .send(NetData::SearchResult(
-                                        videos
-                                            .iter()
-                                            .map(|v| Video {
-                                                id: v.id.to_owned(),
-                                                title: v.title.to_owned(),
-                                                author: v.author.name.to_owned(),
-                                                thumbnail: v.thumbnail.to_owned(),
-                                                duration: v.duration.clone(),
-                                            })
-                                            .collect(),
-                                    ))
-                                    .await
-                                    .unwrap(),
-                            }
-                        }
-                        NetData::Move(index, video_id, delta) => {
-                            let mut playlist = state.list.lock().await;
-                            if index as i32 + delta >= 0 && index as i32 + delta < playlist.len() as i32 && let Some(video) = playlist.get(index) && video.id == video_id {
-                                playlist.swap(index, (index as i32 + delta) as usize);
-                                state
-                                    .music_player_tx
-                                    .send(MusicPlayerMessage::Move(index, video_id.clone(), delta))
-                                    .unwrap();
-                                state.tx.send(NetData::Move(index, video_id, delta)).unwrap();
-                            }
-                        }
-                        NetData::Play => {
-                            log::debug!("Play video");
-                            state
-                                .music_player_tx
-                                .send(MusicPlayerMessage::Play)
-                                .unwrap();
-                        }
-                        NetData::Pause => {
-                            log::debug!("Pause video");
-                            state
-                                .music_player_tx
-                                .send(MusicPlayerMessage::Pause)
-                                .unwrap();
-                            // let mpv_player = state.mpv.lock().await;
-                            // mpv_player.pause().unwrap();
-                        }
-                        NetData::Next => {
-                            log::debug!("Next video");
-                            // TODO : Remove this
-                            // let mpv_player = state.mpv.lock().await;
-                            // mpv_player.playlist_next_force().unwrap();
-                            // tx_single.send(NetData::Next).await.unwrap();
-                        }
-                        NetData::SetVolume(volume) => {
-                            // let mpv_player = state.mpv.lock().await;
-                            // mpv_player.set_property("volume", volume).unwrap();
-                            state
-                                .music_player_tx
-                                .send(MusicPlayerMessage::SetVolume(volume))
-                                .unwrap();
-                        }
-                        _ => (),
-                    },
+                    Ok(NetData::Request(request_id, request)) => {
+                        let reply =
+                            match handle_request(&state, &username, &tx_single, request).await {
+                                Ok(()) => NetData::Ack(request_id),
+                                Err(err) => {
+                                    log::error!("Request {request_id} failed: {}", err.message);
+                                    NetData::Error {
+                                        request_id: Some(request_id),
+                                        kind: err.kind,
+                                        message: err.message,
+                                    }
+                                }
+                            };
+                        tx_single.send(reply).await.unwrap();
+                    }
+                    Ok(msg) => {
+                        log::error!("Unexpected message: {msg:?}");
+                        tx_single
+                            .send(NetData::Error {
+                                request_id: None,
+                                kind: ErrorKind::InvalidRequest,
+                                message: "Only requests are expected from the clients".to_owned(),
+                            })
+                            .await
+                            .unwrap();
+                    }
                     Err(err) => log::error!("Error decoding message: {err}"),
                 },
                 Message::Close(_) => return,
@@ -130,19 +102,38 @@ async fn websocket(stream: WebSocket, state: Arc<AppState>) {
         loop {
             tokio::select! {
                 data_res = rx.recv() => {
-                    if let Ok(data) = data_res {
-                        if let Err(err) = send_data_ws(&mut sender, data).await {
-                            log::error!("Error sending data: {err}");
-                            break;
+                    match data_res {
+                        Ok(data) if data.is_supported_by(&capabilities) => {
+                            if let Err(err) = send_data_ws(&mut sender, data).await {
+                                log::error!("Error sending data: {err}");
+                                break;
+                            }
+                        }
+                        // A client that cannot apply a change of the queue gets the whole new state instead
+                        Ok(data) if data.changes_queue() && snapshot_supported => {
+                            if let Err(err) = send_snapshot(&state_broadcast, &mut sender, &mut rx, &capabilities).await {
+                                log::error!("Error sending snapshot: {err}");
+                                break;
+                            }
                         }
+                        _ => (),
                     }
                 },
                 data_opt = rx_single.recv() => {
-                    if let Some(data) = data_opt {
-                        if let Err(err) = send_data_ws(&mut sender, data).await {
-                            log::error!("Error sending data: {err}");
-                            break;
+                    match data_opt {
+                        Some(NetData::Resync) if snapshot_supported => {
+                            if let Err(err) = send_snapshot(&state_broadcast, &mut sender, &mut rx, &capabilities).await {
+                                log::error!("Error sending snapshot: {err}");
+                                break;
+                            }
                         }
+                        Some(data) if data.is_supported_by(&capabilities) => {
+                            if let Err(err) = send_data_ws(&mut sender, data).await {
+                                log::error!("Error sending data: {err}");
+                                break;
+                            }
+                        }
+                        _ => (),
                     }
                 }
             }
@@ -155,6 +146,603 @@ async fn websocket(stream: WebSocket, state: Arc<AppState>) {
     }
 }
 
+struct RequestError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl RequestError {
+    fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<DbErr> for RequestError {
+    fn from(err: DbErr) -> Self {
+        RequestError::new(ErrorKind::Internal, format!("Database error: {err}"))
+    }
+}
+
+impl<T> From<mpsc::error::SendError<T>> for RequestError {
+    fn from(_: mpsc::error::SendError<T>) -> Self {
+        RequestError::new(ErrorKind::Internal, "The music player is not running")
+    }
+}
+
+/// Apply a request received from a client
+async fn handle_request(
+    state: &AppState,
+    username: &str,
+    tx_single: &mpsc::Sender<NetData>,
+    request: ClientRequest,
+) -> Result<(), RequestError> {
+    match request {
+        ClientRequest::Remove(entry_id) => {
+            log::debug!("Removing entry: {entry_id}");
+            let mut playlist = state.list.lock().await;
+            let index = playlist
+                .iter()
+                .position(|e| e.id == entry_id)
+                .ok_or_else(|| {
+                    RequestError::new(
+                        ErrorKind::NotFound,
+                        "This video is not in the playlist anymore",
+                    )
+                })?;
+            sql::queue::remove(&state.conn, entry_id).await?;
+            playlist.remove(index);
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::RemoveVideo(entry_id))?;
+            state.tx.send(NetData::Remove(entry_id)).unwrap();
+        }
+        ClientRequest::Add(video) => {
+            log::debug!("Adding video: {}", video.title);
+            // The server only fetches the sources enabled in its configuration
+            if !state.resolvers.handles(&video) {
+                return Err(RequestError::new(
+                    ErrorKind::InvalidRequest,
+                    "This source cannot be played by the jukebox",
+                ));
+            }
+            // Only the files of the library can be played from the server
+            if video.id.starts_with("file://")
+                && !sql::track::exists(&state.conn, &video.id).await?
+            {
+                return Err(RequestError::new(
+                    ErrorKind::NotFound,
+                    "This track is not in the library",
+                ));
+            }
+            let shuffle = state.play_mode.lock().await.shuffle;
+            let mut playlist = state.list.lock().await;
+            let entry =
+                sql::queue::push(&state.conn, playlist.len(), &video, username, false).await?;
+            playlist.push(entry.clone());
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::AddMusic(entry.clone()))?;
+            state.tx.send(NetData::Added(entry)).unwrap();
+            // The music is played before the ones queued by the autoplay,
+            // in shuffle mode it is queued anywhere between them and the one playing
+            let index = playlist.len() - 1;
+            let mut new_index = playlist
+                .iter()
+                .skip(1)
+                .position(|e| e.auto_added)
+                .map_or(index, |i| i + 1);
+            if shuffle && new_index > 1 {
+                new_index = 1 + random_below(new_index);
+            }
+            if new_index != index {
+                move_entry(state, &mut playlist, index, new_index).await?;
+            }
+        }
+        ClientRequest::Search(search_txt) => {
+            log::debug!("Search videos: {search_txt}");
+            // Tracks of the library come before the videos of YouTube
+            let mut results: Vec<Video> =
+                sql::track::search(&state.conn, &search_txt, LIBRARY_SEARCH_LIMIT)
+                    .await?
+                    .into_iter()
+                    .map(library::track_video)
+                    .collect();
+            match my_youtube_extractor::search_videos(&search_txt).await {
+                Ok(videos) => results.extend(videos.iter().map(youtube_video)),
+                Err(why) if results.is_empty() => {
+                    return Err(RequestError::new(
+                        ErrorKind::SearchFailed,
+                        format!("Error searching videos : {why}"),
+                    ))
+                }
+                Err(why) => log::error!("Error searching videos : {why}"),
+            }
+            tx_single
+                .send(NetData::SearchResult(results))
+                .await
+                .unwrap();
+        }
+        ClientRequest::Move(entry_id, delta) => {
+            let mut playlist = state.list.lock().await;
+            let index = playlist
+                .iter()
+                .position(|e| e.id == entry_id)
+                .ok_or_else(|| {
+                    RequestError::new(
+                        ErrorKind::NotFound,
+                        "This video is not in the playlist anymore",
+                    )
+                })?;
+            let other_index = index
+                .checked_add_signed(delta as isize)
+                .filter(|other_index| *other_index < playlist.len())
+                .ok_or_else(|| {
+                    RequestError::new(
+                        ErrorKind::InvalidRequest,
+                        "Cannot move this video outside of the playlist",
+                    )
+                })?;
+            sql::queue::swap(&state.conn, entry_id, playlist[other_index].id).await?;
+            playlist.swap(index, other_index);
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::Move(entry_id, delta))?;
+            state.tx.send(NetData::Move(entry_id, delta)).unwrap();
+        }
+        ClientRequest::MoveTo(entry_id, new_index) => {
+            log::debug!("Moving entry {entry_id} to {new_index}");
+            let mut playlist = state.list.lock().await;
+            let index = playlist
+                .iter()
+                .position(|e| e.id == entry_id)
+                .ok_or_else(|| {
+                    RequestError::new(
+                        ErrorKind::NotFound,
+                        "This video is not in the playlist anymore",
+                    )
+                })?;
+            let new_index = new_index as usize;
+            if new_index >= playlist.len() {
+                return Err(RequestError::new(
+                    ErrorKind::InvalidRequest,
+                    "Cannot move this video outside of the playlist",
+                ));
+            }
+            move_entry(state, &mut playlist, index, new_index).await?;
+        }
+        ClientRequest::Play => {
+            log::debug!("Play video");
+            state.music_player_tx.send(MusicPlayerMessage::Play)?;
+        }
+        ClientRequest::Pause => {
+            log::debug!("Pause video");
+            state.music_player_tx.send(MusicPlayerMessage::Pause)?;
+            // let mpv_player = state.mpv.lock().await;
+            // mpv_player.pause().unwrap();
+        }
+        ClientRequest::Next => {
+            log::debug!("Next video");
+            if state.list.lock().await.is_empty() {
+                return Err(RequestError::new(
+                    ErrorKind::InvalidRequest,
+                    "No music is playing",
+                ));
+            }
+            state.music_player_tx.send(MusicPlayerMessage::Skip)?;
+        }
+        ClientRequest::SetVolume(volume) => {
+            if !volume.is_finite() {
+                return Err(RequestError::new(
+                    ErrorKind::InvalidRequest,
+                    "The volume must be a number",
+                ));
+            }
+            let volume = volume.clamp(0.0, 100.0);
+            let mut current_volume = state.volume.lock().await;
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::SetVolume(volume))?;
+            *current_volume = volume;
+            state.tx.send(NetData::SetVolume(volume)).unwrap();
+            drop(current_volume);
+            sql::setting::set(&state.conn, sql::setting::VOLUME, volume.to_string()).await?;
+        }
+        ClientRequest::SetTransitions(transitions) => {
+            log::debug!("Set transitions: {transitions:?}");
+            if transitions.crossfade_ms > Transitions::MAX_CROSSFADE_MS
+                || transitions.fade_ms > Transitions::MAX_FADE_MS
+            {
+                return Err(RequestError::new(
+                    ErrorKind::InvalidRequest,
+                    format!(
+                        "The crossfade is limited to {}ms and the fades to {}ms",
+                        Transitions::MAX_CROSSFADE_MS,
+                        Transitions::MAX_FADE_MS
+                    ),
+                ));
+            }
+            let mut current_transitions = state.transitions.lock().await;
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::SetTransitions(transitions))?;
+            *current_transitions = transitions;
+            state.tx.send(NetData::SetTransitions(transitions)).unwrap();
+            drop(current_transitions);
+            sql::setting::set(
+                &state.conn,
+                sql::setting::CROSSFADE,
+                transitions.crossfade_ms.to_string(),
+            )
+            .await?;
+            sql::setting::set(
+                &state.conn,
+                sql::setting::FADE,
+                transitions.fade_ms.to_string(),
+            )
+            .await?;
+        }
+        ClientRequest::SetNormalization(normalization) => {
+            log::debug!("Set normalization: {normalization:?}");
+            if !(Normalization::MIN_TARGET_LUFS..=Normalization::MAX_TARGET_LUFS)
+                .contains(&normalization.target_lufs)
+            {
+                return Err(RequestError::new(
+                    ErrorKind::InvalidRequest,
+                    format!(
+                        "The target loudness must be between {} and {} LUFS",
+                        Normalization::MIN_TARGET_LUFS,
+                        Normalization::MAX_TARGET_LUFS
+                    ),
+                ));
+            }
+            let mut current_normalization = state.normalization.lock().await;
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::SetNormalization(normalization))?;
+            *current_normalization = normalization;
+            state
+                .tx
+                .send(NetData::SetNormalization(normalization))
+                .unwrap();
+            drop(current_normalization);
+            sql::setting::set(
+                &state.conn,
+                sql::setting::NORMALIZATION_ENABLED,
+                normalization.enabled.to_string(),
+            )
+            .await?;
+            sql::setting::set(
+                &state.conn,
+                sql::setting::NORMALIZATION_TARGET,
+                normalization.target_lufs.to_string(),
+            )
+            .await?;
+        }
+        ClientRequest::SetPlayMode(play_mode) => {
+            log::debug!("Set play mode: {play_mode:?}");
+            let mut playlist = state.list.lock().await;
+            let mut current_play_mode = state.play_mode.lock().await;
+            // The queue is shuffled once, the musics added afterward are queued at a random position
+            let shuffled = play_mode.shuffle && !current_play_mode.shuffle && playlist.len() > 2;
+            if shuffled {
+                // The music playing stays first
+                let mut shuffled_playlist = playlist.clone();
+                for index in 1..shuffled_playlist.len() - 1 {
+                    let other = index + random_below(shuffled_playlist.len() - index);
+                    shuffled_playlist.swap(index, other);
+                }
+                let order: Vec<EntryId> = shuffled_playlist.iter().map(|entry| entry.id).collect();
+                sql::queue::set_order(&state.conn, &order).await?;
+                *playlist = shuffled_playlist;
+                state
+                    .music_player_tx
+                    .send(MusicPlayerMessage::Reorder(order))?;
+            }
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::SetPlayMode(play_mode))?;
+            *current_play_mode = play_mode;
+            state.tx.send(NetData::SetPlayMode(play_mode)).unwrap();
+            drop(current_play_mode);
+            drop(playlist);
+            if shuffled {
+                // The player locks the queue, it is not held while waiting for its status
+                let (status_tx, status_rx) = oneshot::channel();
+                state
+                    .music_player_tx
+                    .send(MusicPlayerMessage::Status(status_tx))?;
+                let status = status_rx.await.map_err(|_| {
+                    RequestError::new(ErrorKind::Internal, "The music player is not running")
+                })?;
+                let playlist = state.list.lock().await;
+                let snapshot = queue_snapshot(state, &playlist, status).await;
+                state.tx.send(snapshot).unwrap();
+                drop(playlist);
+            }
+            sql::setting::set(
+                &state.conn,
+                sql::setting::REPEAT,
+                play_mode.repeat.as_str().to_owned(),
+            )
+            .await?;
+            sql::setting::set(
+                &state.conn,
+                sql::setting::SHUFFLE,
+                play_mode.shuffle.to_string(),
+            )
+            .await?;
+        }
+        ClientRequest::SetEqualizer(equalizer) => {
+            log::debug!("Set equalizer: {equalizer:?}");
+            let equalizer = match equalizer.preset {
+                EqualizerPreset::Custom => {
+                    for gain_db in equalizer.gains_db {
+                        check_gain(gain_db)?;
+                    }
+                    equalizer
+                }
+                preset => Equalizer::preset(preset),
+            };
+            let mut current_equalizer = state.equalizer.lock().await;
+            set_equalizer(state, &mut current_equalizer, equalizer)?;
+            drop(current_equalizer);
+            save_equalizer(state, equalizer).await?;
+        }
+        ClientRequest::SetEqualizerBand(band, gain_db) => {
+            log::debug!("Set equalizer band {band} at {gain_db}dB");
+            if band as usize >= EQUALIZER_BANDS {
+                return Err(RequestError::new(
+                    ErrorKind::InvalidRequest,
+                    format!("The equalizer has {EQUALIZER_BANDS} bands"),
+                ));
+            }
+            check_gain(gain_db)?;
+            let mut current_equalizer = state.equalizer.lock().await;
+            let mut equalizer = Equalizer {
+                preset: EqualizerPreset::Custom,
+                ..*current_equalizer
+            };
+            equalizer.gains_db[band as usize] = gain_db;
+            set_equalizer(state, &mut current_equalizer, equalizer)?;
+            drop(current_equalizer);
+            save_equalizer(state, equalizer).await?;
+        }
+        ClientRequest::SetRate(rate) => {
+            log::debug!("Set playback rate: {rate}");
+            check_rate(rate)?;
+            let mut current_rate = state.rate.lock().await;
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::SetRate(rate))?;
+            *current_rate = rate;
+            state.tx.send(NetData::SetRate(rate)).unwrap();
+            drop(current_rate);
+            sql::setting::set(&state.conn, sql::setting::PLAYBACK_RATE, rate.to_string()).await?;
+        }
+        ClientRequest::SetEntryRate(entry_id, rate) => {
+            log::debug!("Set playback rate of entry {entry_id}: {rate:?}");
+            if let Some(rate) = rate {
+                check_rate(rate)?;
+            }
+            let mut playlist = state.list.lock().await;
+            let entry = playlist
+                .iter_mut()
+                .find(|e| e.id == entry_id)
+                .ok_or_else(|| {
+                    RequestError::new(
+                        ErrorKind::NotFound,
+                        "This video is not in the playlist anymore",
+                    )
+                })?;
+            sql::queue::set_rate(&state.conn, entry_id, rate).await?;
+            entry.rate = rate;
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::SetEntryRate(entry_id, rate))?;
+            state
+                .tx
+                .send(NetData::SetEntryRate(entry_id, rate))
+                .unwrap();
+        }
+        ClientRequest::Seek(position_ms) => {
+            log::debug!("Seek at {position_ms}ms");
+            if state.list.lock().await.is_empty() {
+                return Err(RequestError::new(
+                    ErrorKind::InvalidRequest,
+                    "No music is playing",
+                ));
+            }
+            state
+                .music_player_tx
+                .send(MusicPlayerMessage::Seek(position_ms))?;
+        }
+        ClientRequest::Resync => {
+            log::debug!("Resync asked");
+            tx_single.send(NetData::Resync).await.unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Video of the queue matching a video found on YouTube
+pub fn youtube_video(video: &YtVideoPageInfo) -> Video {
+    Video {
+        id: video.id.to_owned(),
+        title: video.title.to_owned(),
+        author: video.author.name.to_owned(),
+        thumbnail: video.thumbnail.to_owned(),
+        duration: video.duration.clone(),
+    }
+}
+
+/// Move the entry at `index` of the queue to `new_index` for the music player and every client
+async fn move_entry(
+    state: &AppState,
+    playlist: &mut Vec<QueueEntry>,
+    index: usize,
+    new_index: usize,
+) -> Result<(), RequestError> {
+    let entry_id = playlist[index].id;
+    sql::queue::move_to(&state.conn, entry_id, new_index as i32).await?;
+    let entry = playlist.remove(index);
+    playlist.insert(new_index, entry);
+    state
+        .music_player_tx
+        .send(MusicPlayerMessage::MoveTo(entry_id, new_index))?;
+    state
+        .tx
+        .send(NetData::MoveTo(entry_id, new_index as u32))
+        .unwrap();
+    Ok(())
+}
+
+fn check_gain(gain_db: f64) -> Result<(), RequestError> {
+    if !(Equalizer::MIN_GAIN_DB..=Equalizer::MAX_GAIN_DB).contains(&gain_db) {
+        return Err(RequestError::new(
+            ErrorKind::InvalidRequest,
+            format!(
+                "The gains of the equalizer must be between {}dB and {}dB",
+                Equalizer::MIN_GAIN_DB,
+                Equalizer::MAX_GAIN_DB
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn check_rate(rate: f64) -> Result<(), RequestError> {
+    if !(MIN_PLAYBACK_RATE..=MAX_PLAYBACK_RATE).contains(&rate) {
+        return Err(RequestError::new(
+            ErrorKind::InvalidRequest,
+            format!(
+                "The playback rate must be between {MIN_PLAYBACK_RATE} and {MAX_PLAYBACK_RATE}"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Apply an equalizer for the music player and every client, `current` is the locked equalizer of the state
+fn set_equalizer(
+    state: &AppState,
+    current: &mut Equalizer,
+    equalizer: Equalizer,
+) -> Result<(), RequestError> {
+    state
+        .music_player_tx
+        .send(MusicPlayerMessage::SetEqualizer(equalizer))?;
+    *current = equalizer;
+    state.tx.send(NetData::SetEqualizer(equalizer)).unwrap();
+    Ok(())
+}
+
+async fn save_equalizer(state: &AppState, equalizer: Equalizer) -> Result<(), RequestError> {
+    sql::setting::set(
+        &state.conn,
+        sql::setting::EQUALIZER_PRESET,
+        equalizer.preset.as_str().to_owned(),
+    )
+    .await?;
+    let gains: Vec<String> = equalizer.gains_db.iter().map(f64::to_string).collect();
+    sql::setting::set(&state.conn, sql::setting::EQUALIZER_GAINS, gains.join(",")).await?;
+    Ok(())
+}
+
+/// Snapshot of the queue and of the music playing, `playlist` is the locked queue of the state
+async fn queue_snapshot(
+    state: &AppState,
+    playlist: &[QueueEntry],
+    status: PlayerStatus,
+) -> NetData {
+    NetData::Snapshot {
+        queue: playlist.to_vec(),
+        now_playing: playlist.first().cloned(),
+        position_ms: status.position_ms,
+        paused: status.paused,
+        volume: *state.volume.lock().await,
+    }
+}
+
+/// Send the whole state of the jukebox followed by the settings the client supports,
+/// `rx` is renewed so that no broadcast older than the snapshot is sent afterward
+async fn send_snapshot(
+    state: &AppState,
+    sender: &mut SplitSink<WebSocket, Message>,
+    rx: &mut broadcast::Receiver<NetData>,
+    capabilities: &[String],
+) -> Result<()> {
+    let (status_tx, status_rx) = oneshot::channel();
+    state
+        .music_player_tx
+        .send(MusicPlayerMessage::Status(status_tx))?;
+    let status = status_rx.await?;
+
+    let playlist = state.list.lock().await;
+    *rx = state.tx.subscribe();
+    let snapshot = queue_snapshot(state, &playlist, status).await;
+    let settings = [
+        NetData::SetTransitions(*state.transitions.lock().await),
+        NetData::SetNormalization(*state.normalization.lock().await),
+        NetData::SetPlayMode(*state.play_mode.lock().await),
+        NetData::SetEqualizer(*state.equalizer.lock().await),
+        NetData::SetRate(*state.rate.lock().await),
+    ];
+    drop(playlist);
+
+    send_data_ws(sender, snapshot).await?;
+    for setting in settings {
+        if setting.is_supported_by(capabilities) {
+            send_data_ws(sender, setting).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Wait for the Hello of the client and answer with the capabilities both sides support
+async fn handshake(
+    sender: &mut SplitSink<WebSocket, Message>,
+    receiver: &mut SplitStream<WebSocket>,
+) -> Result<Vec<String>, String> {
+    let first_message = tokio::time::timeout(HANDSHAKE_TIMEOUT, receiver.next())
+        .await
+        .map_err(|_| "No Hello message received".to_owned())?;
+    let (protocol_version, client_capabilities) = match first_message {
+        Some(Ok(Message::Binary(data))) => match NetData::decode_message(data.as_slice()) {
+            Ok(NetData::Hello {
+                protocol_version,
+                capabilities,
+            }) => (protocol_version, capabilities),
+            _ => return Err("Outdated client, please reload the page".to_owned()),
+        },
+        _ => return Err("Expected a Hello message".to_owned()),
+    };
+
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version) {
+        return Err(format!(
+            "Unsupported protocol version {protocol_version} (server supports {MIN_PROTOCOL_VERSION} to {PROTOCOL_VERSION}), please reload the page"
+        ));
+    }
+    log::debug!("Client speaks protocol version {protocol_version} with capabilities {client_capabilities:?}");
+
+    let capabilities: Vec<String> = client_capabilities
+        .into_iter()
+        .filter(|c| CAPABILITIES.contains(&c.as_str()))
+        .collect();
+    send_data_ws(
+        sender,
+        NetData::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: capabilities.clone(),
+        },
+    )
+    .await
+    .map_err(|err| err.to_string())?;
+    Ok(capabilities)
+}
+
 async fn send_data_ws(sender: &mut SplitSink<WebSocket, Message>, data: NetData) -> Result<()> {
     let msg = data.encode_message()?;
     sender.send(ws::Message::Binary(msg)).await?;
@@ -0,0 +1,178 @@
+use crate::Route;
+use gloo::net::http::Request;
+use jukebox_rust::{HistoryEntry, HistoryPage, PlayOutcome};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+const PER_PAGE: u64 = 50;
+
+/// Musics of the history to show, sent as the parameters of `/api/history`
+#[derive(Clone, PartialEq, Default)]
+struct HistoryFilter {
+    user: String,   // User who queued the music
+    search: String, // Part of the title
+    outcome: Option<PlayOutcome>,
+}
+
+#[function_component(History)]
+pub fn history() -> Html {
+    let page = use_state(|| 0u64);
+    let filter = use_state(HistoryFilter::default);
+    let history = use_state(|| None::<HistoryPage>);
+    let error = use_state(|| None::<String>);
+
+    {
+        let history = history.clone();
+        let error = error.clone();
+        use_effect_with_deps(
+            move |(page, filter)| {
+                let (page, filter) = (*page, filter.clone());
+                spawn_local(async move {
+                    match fetch_history(page, &filter).await {
+                        Ok(fetched) => {
+                            history.set(Some(fetched));
+                            error.set(None);
+                        }
+                        Err(err) => {
+                            log::error!("Error loading the history: {err}");
+                            error.set(Some(err.to_string()));
+                        }
+                    }
+                });
+                || ()
+            },
+            (*page, (*filter).clone()),
+        );
+    }
+
+    // A new filter shows its first page
+    let set_filter = {
+        let filter = filter.clone();
+        let page = page.clone();
+        Callback::from(move |new_filter: HistoryFilter| {
+            page.set(0);
+            filter.set(new_filter);
+        })
+    };
+    let on_user = {
+        let (filter, set_filter) = ((*filter).clone(), set_filter.clone());
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                set_filter.emit(HistoryFilter {
+                    user: input.value(),
+                    ..filter.clone()
+                });
+            }
+        })
+    };
+    let on_search = {
+        let (filter, set_filter) = ((*filter).clone(), set_filter.clone());
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                set_filter.emit(HistoryFilter {
+                    search: input.value(),
+                    ..filter.clone()
+                });
+            }
+        })
+    };
+    let on_outcome = {
+        let filter = (*filter).clone();
+        Callback::from(move |e: Event| {
+            if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                set_filter.emit(HistoryFilter {
+                    outcome: PlayOutcome::parse(&select.value()),
+                    ..filter.clone()
+                });
+            }
+        })
+    };
+
+    let total = history.as_ref().map_or(0, |history| history.total);
+    let last_page = total.saturating_sub(1) / PER_PAGE;
+    let on_previous = {
+        let page = page.clone();
+        Callback::from(move |_| page.set(page.saturating_sub(1)))
+    };
+    let on_next = {
+        let page = page.clone();
+        Callback::from(move |_| page.set((*page + 1).min(last_page)))
+    };
+
+    html! {
+        <main>
+            <Link<Route> to={ Route::Home }>{ "Back to the jukebox" }</Link<Route>>
+            <h2>{ "History :" }</h2>
+            if let Some(message) = &*error {
+                <p class="error">{ "Cannot load the history: " }{ message }</p>
+            }
+            <div>
+                <input type="search" placeholder="Added by..." value={ filter.user.clone() } onchange={ on_user }/>
+                <input type="search" placeholder="Title..." value={ filter.search.clone() } onchange={ on_search }/>
+                <select onchange={ on_outcome }>
+                    <option value="" selected={ filter.outcome.is_none() }>{ "Any end" }</option>
+                    {
+                        [PlayOutcome::Finished, PlayOutcome::Skipped, PlayOutcome::Failed].iter().map(|outcome| html! {
+                            <option value={ outcome.as_str() } selected={ filter.outcome == Some(*outcome) }>{ outcome.as_str() }</option>
+                        }).collect::<Html>()
+                    }
+                </select>
+            </div>
+            <table>
+                <tr>
+                    <th>{ "Started" }</th>
+                    <th>{ "Title" }</th>
+                    <th>{ "Added by" }</th>
+                    <th>{ "End" }</th>
+                </tr>
+                {
+                    history.as_ref().map(|history| history.entries.iter().map(history_row).collect::<Html>()).unwrap_or_default()
+                }
+            </table>
+            <button onclick={ on_previous } disabled={ *page == 0 }>{ "Previous" }</button>
+            <span>{ "Page " }{ *page + 1 }{ " / " }{ last_page + 1 }{ " (" }{ total }{ " musics)" }</span>
+            <button onclick={ on_next } disabled={ *page >= last_page }>{ "Next" }</button>
+        </main>
+    }
+}
+
+fn history_row(entry: &HistoryEntry) -> Html {
+    html! {
+        <tr>
+            <td>{ format_date(entry.started_at) }</td>
+            <td>{ entry.video.title.clone() }</td>
+            <td>{ if entry.auto_added { "autoplay".to_owned() } else { entry.added_by.clone() } }</td>
+            <td>{ entry.outcome.as_str() }</td>
+        </tr>
+    }
+}
+
+async fn fetch_history(page: u64, filter: &HistoryFilter) -> Result<HistoryPage, gloo::net::Error> {
+    let (page, per_page) = (page.to_string(), PER_PAGE.to_string());
+    let mut params = vec![("page", page.as_str()), ("per_page", per_page.as_str())];
+    if !filter.user.is_empty() {
+        params.push(("user", filter.user.as_str()));
+    }
+    if !filter.search.is_empty() {
+        params.push(("search", filter.search.as_str()));
+    }
+    if let Some(outcome) = filter.outcome {
+        params.push(("outcome", outcome.as_str()));
+    }
+    Request::get("/api/history")
+        .query(params)
+        .send()
+        .await?
+        .json()
+        .await
+}
+
+/// Format a Unix timestamp in seconds as a local date
+fn format_date(timestamp: i64) -> String {
+    js_sys::Date::new(&JsValue::from_f64(timestamp as f64 * 1000.0))
+        .to_locale_string("default", &JsValue::UNDEFINED)
+        .into()
+}
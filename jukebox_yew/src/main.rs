@@ -2,37 +2,56 @@
 
 use entity::video::Model as Video;
 use futures::{SinkExt, StreamExt};
-use gloo::net::http::Request;
-use gloo::net::websocket::{futures::WebSocket, Message};
-use jukebox_rust::NetData;
-use playlist::{PlayListMsg, PlaylistAction};
+use gloo::net::websocket::{futures::WebSocket, Message, WebSocketError};
+use jukebox_rust::{
+    ClientRequest, EntryId, Equalizer, EqualizerPreset, NetData, Normalization, PlayMode,
+    PlaybackState, QueueEntry, Repeat, RequestId, Transitions, MAX_PLAYBACK_RATE,
+    MIN_PLAYBACK_RATE,
+};
+use playlist::{PlayListMsg, PlaylistAction, PlaylistItem};
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
-use web_sys::{window, HtmlInputElement};
+use web_sys::{window, HtmlInputElement, HtmlSelectElement};
 use yew::platform::pinned::mpsc::UnboundedSender;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+mod history;
 mod playlist;
 
 #[derive(Clone, Routable, PartialEq)]
 enum Route {
     #[at("/index")]
     Home,
+    #[at("/history")]
+    History,
 }
 
 fn switch(routes: Route) -> Html {
     log::info!("Routing");
     match routes {
         Route::Home => html! { <PlayListHtml /> },
+        Route::History => html! { <history::History /> },
     }
 }
 
 pub struct PlayListHtml {
-    pub playlist: Vec<Video>,
+    pub playlist: Vec<QueueEntry>,
     pub search_videos: Vec<Video>,
-    pub send: UnboundedSender<NetData>,
+    pub send: UnboundedSender<ClientRequest>,
     pub volume: f64,
+    pub transitions: Transitions,
+    pub normalization: Normalization,
+    pub play_mode: PlayMode,
+    pub equalizer: Equalizer,
+    pub rate: f64, // Playback rate of the entries without their own one
+    pub paused: bool,
+    pub buffering: bool, // The server waits for the music to be downloaded
+    pub now_playing: Option<QueueEntry>,
+    pub position_ms: u64,
+    pub duration_ms: Option<u64>,
+    pub connection_error: Option<String>,
+    pub last_error: Option<String>,
 }
 
 impl Component for PlayListHtml {
@@ -40,24 +59,28 @@ impl Component for PlayListHtml {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
-        ctx.link().send_future(async {
-            let resp = Request::get("/api/playlist").send().await.unwrap();
-            let playlist_res =
-                serde_json::from_str::<Vec<Video>>(&resp.text().await.unwrap()).unwrap();
-            PlayListMsg::Load(playlist_res)
-        });
-
         let ws = WebSocket::open("ws://127.0.0.1:4000/websocket").unwrap();
 
         let (mut write_ws, mut read_ws) = ws.split();
-        let (in_tx, mut in_rx) = yew::platform::pinned::mpsc::unbounded::<NetData>();
-        //let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<NetData>(1000);
+        let (in_tx, mut in_rx) = yew::platform::pinned::mpsc::unbounded::<ClientRequest>();
+        //let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<ClientRequest>(1000);
 
         spawn_local(async move {
+            // The server expects a Hello before anything else
+            if let Err(err) = write_ws
+                .send(Message::Bytes(NetData::hello().encode_message().unwrap()))
+                .await
+            {
+                log::error!("Error sending Hello: {err}");
+            }
+            let mut request_id: RequestId = 0;
             while let Some(data) = in_rx.next().await {
-                log::debug!("Send to WebSocket");
+                request_id = request_id.wrapping_add(1);
+                log::debug!("Send request {request_id} to WebSocket");
                 write_ws
-                    .send(Message::Bytes(data.encode_message().unwrap()))
+                    .send(Message::Bytes(
+                        NetData::Request(request_id, data).encode_message().unwrap(),
+                    ))
                     .await
                     .unwrap();
             }
@@ -66,19 +89,52 @@ impl Component for PlayListHtml {
         let link = ctx.link().clone();
 
         spawn_local(async move {
-            while let Some(Ok(msg)) = read_ws.next().await {
+            while let Some(msg_res) = read_ws.next().await {
                 log::debug!("Receive from WebSocket");
+                let msg = match msg_res {
+                    Ok(msg) => msg,
+                    Err(WebSocketError::ConnectionClose(event)) => {
+                        link.send_message(PlayListMsg::Disconnected(event.reason));
+                        break;
+                    }
+                    Err(err) => {
+                        link.send_message(PlayListMsg::Disconnected(err.to_string()));
+                        break;
+                    }
+                };
                 match msg {
                     Message::Bytes(data_encoded) => {
                         match NetData::decode_message(data_encoded.as_slice()) {
                             Ok(data) => match data {
-                                NetData::Remove(index, video_id) => {
+                                NetData::Hello {
+                                    protocol_version,
+                                    capabilities,
+                                } => {
+                                    log::info!("Connected with protocol version {protocol_version} and capabilities {capabilities:?}");
+                                }
+                                NetData::Snapshot {
+                                    queue,
+                                    now_playing,
+                                    position_ms,
+                                    paused,
+                                    volume,
+                                } => {
+                                    log::info!("Snapshot received");
+                                    link.send_message(PlayListMsg::Snapshot {
+                                        queue,
+                                        now_playing,
+                                        position_ms,
+                                        paused,
+                                        volume,
+                                    });
+                                }
+                                NetData::Remove(entry_id) => {
                                     log::info!("Remove video");
-                                    link.send_message(PlayListMsg::Remove(index, video_id));
+                                    link.send_message(PlayListMsg::Remove(entry_id));
                                 }
-                                NetData::Add(video) => {
+                                NetData::Added(entry) => {
                                     log::info!("Add video");
-                                    link.send_message(PlayListMsg::Add(video));
+                                    link.send_message(PlayListMsg::Add(entry));
                                 }
                                 NetData::SearchResult(search_videos) => {
                                     log::info!("Search videos received");
@@ -88,9 +144,71 @@ impl Component for PlayListHtml {
                                     log::info!("Video Passed");
                                     link.send_message(PlayListMsg::Next);
                                 }
-                                NetData::Move(index, video_id, delta) => {
-                                    log::info!("Video move from {} to {}", index, index as i32 + delta);
-                                    link.send_message(PlayListMsg::Move(index, video_id, delta));
+                                NetData::Move(entry_id, delta) => {
+                                    log::info!("Entry {} moved by {}", entry_id, delta);
+                                    link.send_message(PlayListMsg::Move(entry_id, delta));
+                                }
+                                NetData::SetVolume(volume) => {
+                                    log::info!("Volume set to {}", volume);
+                                    link.send_message(PlayListMsg::VolumeChanged(volume));
+                                }
+                                NetData::SetTransitions(transitions) => {
+                                    log::info!("Transitions set to {:?}", transitions);
+                                    link.send_message(PlayListMsg::TransitionsChanged(transitions));
+                                }
+                                NetData::SetNormalization(normalization) => {
+                                    log::info!("Normalization set to {:?}", normalization);
+                                    link.send_message(PlayListMsg::NormalizationChanged(
+                                        normalization,
+                                    ));
+                                }
+                                NetData::SetPlayMode(play_mode) => {
+                                    log::info!("Play mode set to {:?}", play_mode);
+                                    link.send_message(PlayListMsg::PlayModeChanged(play_mode));
+                                }
+                                NetData::SetEqualizer(equalizer) => {
+                                    log::info!("Equalizer set to {:?}", equalizer);
+                                    link.send_message(PlayListMsg::EqualizerChanged(equalizer));
+                                }
+                                NetData::SetRate(rate) => {
+                                    log::info!("Playback rate set to {}", rate);
+                                    link.send_message(PlayListMsg::RateChanged(rate));
+                                }
+                                NetData::SetEntryRate(entry_id, rate) => {
+                                    log::info!("Playback rate of {} set to {:?}", entry_id, rate);
+                                    link.send_message(PlayListMsg::EntryRateChanged(
+                                        entry_id, rate,
+                                    ));
+                                }
+                                NetData::MoveTo(entry_id, new_index) => {
+                                    log::info!("Entry {} moved to {}", entry_id, new_index);
+                                    link.send_message(PlayListMsg::MoveTo(entry_id, new_index));
+                                }
+                                NetData::NowPlaying {
+                                    entry,
+                                    position_ms,
+                                    duration_ms,
+                                    state,
+                                } => {
+                                    link.send_message(PlayListMsg::NowPlaying {
+                                        entry,
+                                        position_ms,
+                                        duration_ms,
+                                        state,
+                                    });
+                                }
+                                NetData::Ack(request_id) => {
+                                    log::debug!("Request {request_id} done");
+                                }
+                                NetData::Error {
+                                    request_id,
+                                    kind,
+                                    message,
+                                } => {
+                                    log::error!(
+                                        "Request {request_id:?} failed ({kind:?}): {message}"
+                                    );
+                                    link.send_message(PlayListMsg::Error(message));
                                 }
                                 _ => {}
                             },
@@ -108,13 +226,48 @@ impl Component for PlayListHtml {
             search_videos: vec![],
             send: in_tx,
             volume: 100.0,
+            transitions: Transitions::default(),
+            normalization: Normalization::default(),
+            play_mode: PlayMode::default(),
+            equalizer: Equalizer::default(),
+            rate: 1.0,
+            paused: true,
+            buffering: false,
+            now_playing: None,
+            position_ms: 0,
+            duration_ms: None,
+            connection_error: None,
+            last_error: None,
         }
     }
 
     fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            PlayListMsg::Load(v) => {
-                self.playlist = v;
+            PlayListMsg::Snapshot {
+                queue,
+                now_playing,
+                position_ms,
+                paused,
+                volume,
+            } => {
+                self.playlist = queue;
+                self.now_playing = now_playing;
+                self.position_ms = position_ms;
+                self.paused = paused;
+                self.volume = volume;
+                true
+            }
+            PlayListMsg::NowPlaying {
+                entry,
+                position_ms,
+                duration_ms,
+                state,
+            } => {
+                self.now_playing = entry;
+                self.position_ms = position_ms;
+                self.duration_ms = duration_ms;
+                self.paused = !matches!(state, PlaybackState::Playing | PlaybackState::Buffering);
+                self.buffering = state == PlaybackState::Buffering;
                 true
             }
             PlayListMsg::List(v) => {
@@ -122,55 +275,66 @@ impl Component for PlayListHtml {
                 true
             }
             PlayListMsg::Search(data) => {
-                if let Err(err) = self.send.send_now(NetData::Search(data)) {
+                if let Err(err) = self.send.send_now(ClientRequest::Search(data)) {
                     log::error!("Can't send data to MPSC channel: {err}");
                 }
                 false
             }
-            PlayListMsg::Remove(index, video_id) => {
-                if let Some(video) = self.playlist.get(index) {
-                    if video.id == video_id {
-                        self.playlist.remove(index);
-                    } else {
-                        log::error!("Trying to remove a video that is not present");
-                    }
+            PlayListMsg::Remove(entry_id) => {
+                if let Some(index) = self.playlist.iter().position(|e| e.id == entry_id) {
+                    self.playlist.remove(index);
+                } else {
+                    log::error!("Trying to remove a video that is not present");
+                    self.resync();
                 }
                 true
             }
-            PlayListMsg::Add(video) => {
+            PlayListMsg::Add(entry) => {
                 log::debug!("Add get");
-                self.playlist.push(video);
+                self.playlist.push(entry);
                 true
             }
-            PlayListMsg::Move(index, video_id, delta) => {
-                if index as i32 + delta >= 0 && index as i32 + delta < self.playlist.len() as i32 && let Some(video) = self.playlist.get(index) && video.id == video_id {
+            PlayListMsg::Move(entry_id, delta) => {
+                if let Some(index) = self.playlist.iter().position(|e| e.id == entry_id) && index as i32 + delta >= 0 && index as i32 + delta < self.playlist.len() as i32 {
                     self.playlist.swap(index, (index as i32 + delta) as usize);
                     true
                 }
                 else {
+                    self.resync();
+                    false
+                }
+            }
+            PlayListMsg::MoveTo(entry_id, new_index) => {
+                if let Some(index) = self.playlist.iter().position(|e| e.id == entry_id) && (new_index as usize) < self.playlist.len() {
+                    let entry = self.playlist.remove(index);
+                    self.playlist.insert(new_index as usize, entry);
+                    true
+                }
+                else {
+                    self.resync();
                     false
                 }
             }
-            PlayListMsg::MoveUp(index, video_id) => {
-                if let Err(err) = self.send.send_now(NetData::Move(index, video_id, -1)) {
+            PlayListMsg::MoveUp(entry_id) => {
+                if let Err(err) = self.send.send_now(ClientRequest::Move(entry_id, -1)) {
                     log::error!("Can't send data to MPSC channel: {err}");
                 }
                 false
             }
-            PlayListMsg::MoveDown(index, video_id) => {
-                if let Err(err) = self.send.send_now(NetData::Move(index, video_id, 1)) {
+            PlayListMsg::MoveDown(entry_id) => {
+                if let Err(err) = self.send.send_now(ClientRequest::Move(entry_id, 1)) {
                     log::error!("Can't send data to MPSC channel: {err}");
                 }
                 false
             }
             PlayListMsg::Play => {
-                if let Err(err) = self.send.send_now(NetData::Play) {
+                if let Err(err) = self.send.send_now(ClientRequest::Play) {
                     log::error!("Can't send data to MPSC channel: {err}");
                 }
                 false
             }
             PlayListMsg::Pause => {
-                if let Err(err) = self.send.send_now(NetData::Pause) {
+                if let Err(err) = self.send.send_now(ClientRequest::Pause) {
                     log::error!("Can't send data to MPSC channel: {err}");
                 }
                 false
@@ -182,62 +346,172 @@ impl Component for PlayListHtml {
                 true
             }
             PlayListMsg::SetVolume(volume) => {
-                if let Err(err) = self.send.send_now(NetData::SetVolume(volume)) {
+                if let Err(err) = self.send.send_now(ClientRequest::SetVolume(volume)) {
+                    log::error!("Can't send data to MPSC channel: {err}");
+                }
+                false
+            }
+            PlayListMsg::VolumeChanged(volume) => {
+                self.volume = volume;
+                true
+            }
+            PlayListMsg::SetTransitions(transitions) => {
+                if let Err(err) = self
+                    .send
+                    .send_now(ClientRequest::SetTransitions(transitions))
+                {
+                    log::error!("Can't send data to MPSC channel: {err}");
+                }
+                false
+            }
+            PlayListMsg::TransitionsChanged(transitions) => {
+                self.transitions = transitions;
+                true
+            }
+            PlayListMsg::SetNormalization(normalization) => {
+                if let Err(err) = self
+                    .send
+                    .send_now(ClientRequest::SetNormalization(normalization))
+                {
+                    log::error!("Can't send data to MPSC channel: {err}");
+                }
+                false
+            }
+            PlayListMsg::NormalizationChanged(normalization) => {
+                self.normalization = normalization;
+                true
+            }
+            PlayListMsg::SetPlayMode(play_mode) => {
+                if let Err(err) = self.send.send_now(ClientRequest::SetPlayMode(play_mode)) {
+                    log::error!("Can't send data to MPSC channel: {err}");
+                }
+                false
+            }
+            PlayListMsg::PlayModeChanged(play_mode) => {
+                self.play_mode = play_mode;
+                true
+            }
+            PlayListMsg::SetEqualizer(equalizer) => {
+                if let Err(err) = self.send.send_now(ClientRequest::SetEqualizer(equalizer)) {
+                    log::error!("Can't send data to MPSC channel: {err}");
+                }
+                false
+            }
+            PlayListMsg::SetEqualizerBand(band, gain_db) => {
+                if let Err(err) = self
+                    .send
+                    .send_now(ClientRequest::SetEqualizerBand(band, gain_db))
+                {
+                    log::error!("Can't send data to MPSC channel: {err}");
+                }
+                false
+            }
+            PlayListMsg::EqualizerChanged(equalizer) => {
+                self.equalizer = equalizer;
+                true
+            }
+            PlayListMsg::SetRate(rate) => {
+                if let Err(err) = self.send.send_now(ClientRequest::SetRate(rate)) {
+                    log::error!("Can't send data to MPSC channel: {err}");
+                }
+                false
+            }
+            PlayListMsg::RateChanged(rate) => {
+                self.rate = rate;
+                true
+            }
+            PlayListMsg::EntryRateChanged(entry_id, rate) => {
+                let entries = self.playlist.iter_mut().chain(self.now_playing.as_mut());
+                for entry in entries.filter(|entry| entry.id == entry_id) {
+                    entry.rate = rate;
+                }
+                true
+            }
+            PlayListMsg::Seek(position_ms) => {
+                if let Err(err) = self.send.send_now(ClientRequest::Seek(position_ms)) {
                     log::error!("Can't send data to MPSC channel: {err}");
                 }
                 false
             }
+            PlayListMsg::Error(message) => {
+                self.last_error = Some(message);
+                true
+            }
+            PlayListMsg::DismissError => {
+                self.last_error = None;
+                true
+            }
+            PlayListMsg::Disconnected(reason) => {
+                log::error!("WebSocket closed: {reason}");
+                self.connection_error = Some(reason);
+                true
+            }
         }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let sender = self.send.clone();
-        let cb_remove = PlaylistAction::Remove(Callback::from(
-            move |(video_index, video_id): (usize, String)| {
-                log::debug!("Removing id: {}", video_id);
-                let _ = sender.send_now(NetData::Remove(video_index, video_id));
-            },
-        ));
+        let cb_remove = PlaylistAction::Remove(Callback::from(move |entry_id: EntryId| {
+            log::debug!("Removing entry: {}", entry_id);
+            let _ = sender.send_now(ClientRequest::Remove(entry_id));
+        }));
 
         let sender = self.send.clone();
         let cb_add = PlaylistAction::Add(Callback::from(move |video: Video| {
-            let _ = sender.send_now(NetData::Add(video));
+            let _ = sender.send_now(ClientRequest::Add(video));
         }));
 
         let sender = self.send.clone();
-        let cb_move_up = PlaylistAction::MoveUp(Callback::from(
-            move |(video_index, video_id): (usize, String)| {
-                log::debug!("Move up: {}", video_id);
-                let _ = sender.send_now(NetData::Move(video_index, video_id, -1));
-            },
-        ));
+        let cb_move_up = PlaylistAction::MoveUp(Callback::from(move |entry_id: EntryId| {
+            log::debug!("Move up: {}", entry_id);
+            let _ = sender.send_now(ClientRequest::Move(entry_id, -1));
+        }));
+
+        let sender = self.send.clone();
+        let cb_move_down = PlaylistAction::MoveDown(Callback::from(move |entry_id: EntryId| {
+            log::debug!("Move down: {}", entry_id);
+            let _ = sender.send_now(ClientRequest::Move(entry_id, 1));
+        }));
+
+        let sender = self.send.clone();
+        let cb_play_next = PlaylistAction::PlayNext(Callback::from(move |entry_id: EntryId| {
+            log::debug!("Play next: {}", entry_id);
+            // The first entry is the one playing
+            let _ = sender.send_now(ClientRequest::MoveTo(entry_id, 1));
+        }));
 
         let sender = self.send.clone();
-        let cb_move_down = PlaylistAction::MoveDown(Callback::from(
-            move |(video_index, video_id): (usize, String)| {
-                log::debug!("Move up: {}", video_id);
-                let _ = sender.send_now(NetData::Move(video_index, video_id, 1));
+        let cb_set_rate = PlaylistAction::SetRate(Callback::from(
+            move |(entry_id, rate): (EntryId, Option<f64>)| {
+                log::debug!("Set the playback rate of {} to {:?}", entry_id, rate);
+                let _ = sender.send_now(ClientRequest::SetEntryRate(entry_id, rate));
             },
         ));
 
+        let sender = self.send.clone();
+        let cb_move_to = Callback::from(move |(entry_id, new_index): (EntryId, u32)| {
+            log::debug!("Move {} to {}", entry_id, new_index);
+            let _ = sender.send_now(ClientRequest::MoveTo(entry_id, new_index));
+        });
+
         let sender = self.send.clone();
         let cb_play = Callback::from(move |_| {
-            let _ = sender.send_now(NetData::Play);
+            let _ = sender.send_now(ClientRequest::Play);
         });
 
         let sender = self.send.clone();
         let cb_pause = Callback::from(move |_| {
-            let _ = sender.send_now(NetData::Pause);
+            let _ = sender.send_now(ClientRequest::Pause);
         });
 
         let sender = self.send.clone();
         let cb_next = Callback::from(move |_| {
-            let _ = sender.send_now(NetData::Next);
+            let _ = sender.send_now(ClientRequest::Next);
         });
 
         let sender = self.send.clone();
         let cb_send_msg = Callback::from(move |search: String| {
-            let _ = sender.send_now(NetData::Search(search));
+            let _ = sender.send_now(ClientRequest::Search(search));
         });
         let cb_search = Callback::from(move |ev: SubmitEvent| {
             ev.prevent_default();
@@ -253,36 +527,263 @@ impl Component for PlayListHtml {
             }
         });
 
-        let cb_change_volume = ctx.link().callback(PlayListMsg::SetVolume);
+        let cb_seek = ctx.link().callback(PlayListMsg::Seek);
+        let on_seek = Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cb_seek.emit(input.value_as_number() as u64);
+            }
+        });
+
+        // The slider follows the mouse, the volume is only sent once it is released
+        let cb_move_volume = ctx.link().callback(PlayListMsg::VolumeChanged);
         let oninput = Callback::from(move |e: InputEvent| {
             if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
-                cb_change_volume.clone().emit(input.value_as_number());
+                cb_move_volume.emit(input.value_as_number());
+            }
+        });
+        let cb_change_volume = ctx.link().callback(PlayListMsg::SetVolume);
+        let on_volume = Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cb_change_volume.emit(input.value_as_number());
+            }
+        });
+
+        let transitions = self.transitions;
+        let cb_set_transitions = ctx.link().callback(PlayListMsg::SetTransitions);
+        let cb_set_crossfade = cb_set_transitions.clone();
+        let on_crossfade = Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cb_set_crossfade.emit(Transitions {
+                    crossfade_ms: input.value_as_number() as u32,
+                    ..transitions
+                });
+            }
+        });
+        let on_fade = Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cb_set_transitions.emit(Transitions {
+                    fade_ms: input.value_as_number() as u32,
+                    ..transitions
+                });
+            }
+        });
+
+        let normalization = self.normalization;
+        let cb_set_normalization = ctx.link().callback(PlayListMsg::SetNormalization);
+        let cb_toggle_normalization = cb_set_normalization.clone();
+        let on_toggle_normalization = Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cb_toggle_normalization.emit(Normalization {
+                    enabled: input.checked(),
+                    ..normalization
+                });
+            }
+        });
+        let on_target_loudness = Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cb_set_normalization.emit(Normalization {
+                    target_lufs: input.value_as_number(),
+                    ..normalization
+                });
             }
         });
 
+        let play_mode = self.play_mode;
+        let cb_set_play_mode = ctx.link().callback(PlayListMsg::SetPlayMode);
+        let cb_toggle_shuffle = cb_set_play_mode.clone();
+        let on_toggle_shuffle = Callback::from(move |_| {
+            cb_toggle_shuffle.emit(PlayMode {
+                shuffle: !play_mode.shuffle,
+                ..play_mode
+            });
+        });
+        let on_cycle_repeat = Callback::from(move |_| {
+            cb_set_play_mode.emit(PlayMode {
+                repeat: match play_mode.repeat {
+                    Repeat::Off => Repeat::One,
+                    Repeat::One => Repeat::All,
+                    Repeat::All => Repeat::Off,
+                },
+                ..play_mode
+            });
+        });
+
+        let cb_set_global_rate = ctx.link().callback(PlayListMsg::SetRate);
+        let on_rate = Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cb_set_global_rate.emit(input.value_as_number());
+            }
+        });
+
+        let cb_set_equalizer = ctx.link().callback(PlayListMsg::SetEqualizer);
+        let on_equalizer_preset = Callback::from(move |e: Event| {
+            if let Some(select) = e.target_dyn_into::<HtmlSelectElement>()
+                && let Some(preset) = EqualizerPreset::parse(&select.value())
+            {
+                cb_set_equalizer.emit(Equalizer::preset(preset));
+            }
+        });
+        let equalizer_bands = Equalizer::FREQUENCIES_HZ
+            .iter()
+            .zip(self.equalizer.gains_db)
+            .enumerate()
+            .map(|(band, (frequency, gain_db))| {
+                let cb_set_band = ctx.link().callback(move |gain_db| {
+                    PlayListMsg::SetEqualizerBand(band as u8, gain_db)
+                });
+                let on_band = Callback::from(move |e: Event| {
+                    if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                        cb_set_band.emit(input.value_as_number());
+                    }
+                });
+                html! {
+                    <label>
+                        { format_frequency(*frequency) }
+                        <input type="range"
+                                value={ gain_db.to_string() }
+                                class="slider__input"
+                                min={ Equalizer::MIN_GAIN_DB.to_string() } max={ Equalizer::MAX_GAIN_DB.to_string() } step=1
+                                onchange={ on_band }
+                        />
+                        { gain_db }{ "dB" }
+                    </label>
+                }
+            })
+            .collect::<Html>();
+
         html! {
             <main>
+                if let Some(reason) = &self.connection_error {
+                    <p class="error">{ "Disconnected from the jukebox: " }{ reason }</p>
+                }
+                if let Some(message) = &self.last_error {
+                    <p class="error">
+                        { message }
+                        <button onclick={ ctx.link().callback(|_| PlayListMsg::DismissError) }>{ "Dismiss" }</button>
+                    </p>
+                }
+                <Link<Route> to={ Route::History }>{ "History" }</Link<Route>>
                 <form onsubmit={ cb_search }>
                     <input type="search" id="search" name="search" placeholder="Search..." minlength=2/>
                 </form>
                 <button onclick={ cb_play.clone() }>{ "Play" }</button>
                 <button onclick={ cb_pause.clone() }>{ "Pause" }</button>
                 <button onclick={ cb_next.clone() }>{ "Next" }</button>
+                <button onclick={ on_toggle_shuffle }>{ if self.play_mode.shuffle { "Shuffle : on" } else { "Shuffle : off" } }</button>
+                <button onclick={ on_cycle_repeat }>{ "Repeat : " }{ self.play_mode.repeat.as_str() }</button>
+                <span>{ if self.buffering { "Buffering" } else if self.paused { "Paused" } else { "Playing" } }</span>
+                if let Some(entry) = &self.now_playing {
+                    <div>
+                        <p>{ "Now playing : " }{ entry.video.title.clone() }</p>
+                        <input type="range"
+                                value={ self.position_ms.to_string() }
+                                class="slider__input"
+                                min=0 max={ self.duration_ms.unwrap_or_default().to_string() } step=1000
+                                onchange={ on_seek }
+                        />
+                        <span>
+                            { format_time(self.position_ms) }
+                            { " / " }
+                            { self.duration_ms.map(format_time).unwrap_or_default() }
+                        </span>
+                    </div>
+                }
                 <input type="range"
                         value={self.volume.to_string()}
                         class="slider__input"
                         min=0 max=100 step=1
                         {oninput}
+                        onchange={ on_volume }
                 />
+                <div>
+                    <label>{ "Crossfade : " }{ format_time(self.transitions.crossfade_ms as u64) }</label>
+                    <input type="range"
+                            value={ self.transitions.crossfade_ms.to_string() }
+                            class="slider__input"
+                            min=0 max={ Transitions::MAX_CROSSFADE_MS.to_string() } step=500
+                            onchange={ on_crossfade }
+                    />
+                    <label>{ "Fade : " }{ self.transitions.fade_ms }{ "ms" }</label>
+                    <input type="range"
+                            value={ self.transitions.fade_ms.to_string() }
+                            class="slider__input"
+                            min=0 max={ Transitions::MAX_FADE_MS.to_string() } step=100
+                            onchange={ on_fade }
+                    />
+                </div>
+                <div>
+                    <label>
+                        <input type="checkbox"
+                                checked={ self.normalization.enabled }
+                                onchange={ on_toggle_normalization }
+                        />
+                        { "Normalize loudness" }
+                    </label>
+                    <label>{ "Target : " }{ self.normalization.target_lufs }{ " LUFS" }</label>
+                    <input type="range"
+                            value={ self.normalization.target_lufs.to_string() }
+                            class="slider__input"
+                            min={ Normalization::MIN_TARGET_LUFS.to_string() } max={ Normalization::MAX_TARGET_LUFS.to_string() } step=1
+                            disabled={ !self.normalization.enabled }
+                            onchange={ on_target_loudness }
+                    />
+                </div>
+                <div>
+                    <label>{ "Speed : " }{ format!("{}x", self.rate) }</label>
+                    <input type="range"
+                            value={ self.rate.to_string() }
+                            class="slider__input"
+                            min={ MIN_PLAYBACK_RATE.to_string() } max={ MAX_PLAYBACK_RATE.to_string() } step=0.05
+                            onchange={ on_rate }
+                    />
+                </div>
+                <div>
+                    <label>{ "Equalizer : " }</label>
+                    <select onchange={ on_equalizer_preset }>
+                        {
+                            EqualizerPreset::ALL.iter().map(|preset| html! {
+                                <option value={ preset.as_str() } selected={ self.equalizer.preset == *preset }>{ preset.as_str() }</option>
+                            }).collect::<Html>()
+                        }
+                        if self.equalizer.preset == EqualizerPreset::Custom {
+                            <option value={ EqualizerPreset::Custom.as_str() } selected=true disabled=true>{ EqualizerPreset::Custom.as_str() }</option>
+                        }
+                    </select>
+                    { equalizer_bands }
+                </div>
                 <h2>{"Playlist :"}</h2>
-                <playlist::Playlist id={"videos"} playlist={ self.playlist.clone() } callbacks={ vec![cb_remove, cb_move_up, cb_move_down] } />
+                <playlist::Playlist id={"videos"} playlist={ self.playlist.iter().cloned().map(PlaylistItem::Entry).collect::<Vec<_>>() } callbacks={ vec![cb_remove, cb_move_up, cb_move_down, cb_play_next, cb_set_rate] } on_move={ cb_move_to } />
                 <h2>{ "Searched :" }</h2>
-                <playlist::Playlist id={"search"} playlist={ self.search_videos.clone() } callbacks={ vec![cb_add] } />
+                <playlist::Playlist id={"search"} playlist={ self.search_videos.iter().cloned().map(PlaylistItem::Video).collect::<Vec<_>>() } callbacks={ vec![cb_add] } />
             </main>
         }
     }
 }
 
+impl PlayListHtml {
+    /// Ask the server for the whole state when the local playlist is out of sync
+    fn resync(&self) {
+        if let Err(err) = self.send.send_now(ClientRequest::Resync) {
+            log::error!("Can't send data to MPSC channel: {err}");
+        }
+    }
+}
+
+/// Format a frequency in Hz, in kHz above 1000
+fn format_frequency(hz: u32) -> String {
+    if hz < 1000 {
+        format!("{hz}Hz")
+    } else {
+        format!("{:.1}kHz", hz as f64 / 1000.0)
+    }
+}
+
+/// Format milliseconds as minutes:seconds
+fn format_time(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
 #[function_component(App)]
 fn app() -> Html {
     html! {
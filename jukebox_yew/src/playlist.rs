@@ -1,56 +1,144 @@
 use entity::video::Model as Video;
+use jukebox_rust::{
+    EntryId, Equalizer, Normalization, PlayMode, PlaybackState, QueueEntry, Transitions,
+};
+use web_sys::HtmlSelectElement;
 use yew::prelude::*;
 
+/// Playback rates offered for an entry of the queue
+const ENTRY_RATES: [f64; 7] = [0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0];
+
 pub enum PlayListMsg {
-    Load(Vec<Video>),
+    Snapshot {
+        queue: Vec<QueueEntry>,
+        now_playing: Option<QueueEntry>,
+        position_ms: u64,
+        paused: bool,
+        volume: f64,
+    },
+    NowPlaying {
+        entry: Option<QueueEntry>,
+        position_ms: u64,
+        duration_ms: Option<u64>,
+        state: PlaybackState,
+    },
     Search(String),
     List(Vec<Video>),
-    Remove(usize, String), // Index and id of the video
-    Add(Video),
-    MoveUp(usize, String), // Index and id of the video
-    MoveDown(usize, String), // Index and id of the video
-    Move(usize, String, i32), // Index | Video id | Delta of the move in the playlist
+    Remove(EntryId),
+    Add(QueueEntry),
+    MoveUp(EntryId),
+    MoveDown(EntryId),
+    Move(EntryId, i32),   // Entry | Delta of the move in the playlist
+    MoveTo(EntryId, u32), // Entry | New index in the playlist
     Play,
     Pause,
     Next,
     SetVolume(f64),
+    VolumeChanged(f64), // Volume set by any client
+    SetTransitions(Transitions),
+    TransitionsChanged(Transitions), // Transitions set by any client
+    SetNormalization(Normalization),
+    NormalizationChanged(Normalization), // Normalization set by any client
+    SetPlayMode(PlayMode),
+    PlayModeChanged(PlayMode), // Repeat and shuffle modes set by any client
+    SetEqualizer(Equalizer),
+    SetEqualizerBand(u8, f64),   // Band | Gain in dB
+    EqualizerChanged(Equalizer), // Equalizer set by any client
+    SetRate(f64),
+    RateChanged(f64),                       // Global playback rate set by any client
+    EntryRateChanged(EntryId, Option<f64>), // Entry | Its own rate, None for the global one
+    Seek(u64),                              // Position in milliseconds
+    Error(String),                          // Why a request failed
+    DismissError,
+    Disconnected(String), // Reason of the close of the WebSocket
 }
 
 #[derive(PartialEq, Clone)]
 pub enum PlaylistAction {
     Add(Callback<Video>),
-    Remove(Callback<(usize, String)>),
-    MoveUp(Callback<(usize, String)>),
-    MoveDown(Callback<(usize, String)>),
+    Remove(Callback<EntryId>),
+    MoveUp(Callback<EntryId>),
+    MoveDown(Callback<EntryId>),
+    PlayNext(Callback<EntryId>),
+    SetRate(Callback<(EntryId, Option<f64>)>), // Entry | Its own playback rate, None for the global one
+}
+
+#[derive(PartialEq, Clone)]
+pub enum PlaylistItem {
+    Entry(QueueEntry), // Video in the queue
+    Video(Video),      // Video found by a search
+}
+
+impl PlaylistItem {
+    pub fn video(&self) -> &Video {
+        match self {
+            PlaylistItem::Entry(entry) => &entry.video,
+            PlaylistItem::Video(video) => video,
+        }
+    }
 }
 
 #[derive(Properties, PartialEq)]
 pub struct PlaylistProp {
     pub id: String,
-    pub playlist: Vec<Video>,
+    pub playlist: Vec<PlaylistItem>,
     pub callbacks: Vec<PlaylistAction>,
+    #[prop_or_default]
+    pub on_move: Option<Callback<(EntryId, u32)>>, // Entry dropped | Index where it was dropped
 }
 
 #[function_component(Playlist)]
 pub fn playlist(props: &PlaylistProp) -> Html {
+    // Entry being dragged to another place of the queue
+    let dragged = use_state(|| None::<EntryId>);
     html! {
         <ul id={ props.id.clone() }>
             {
-                props.playlist.clone().iter().enumerate().map(|(i, v)| html! {
-                    <li id={ v.id.clone() }>
+                props.playlist.clone().iter().enumerate().map(|(index, item)| {
+                    let (draggable, ondragstart, ondragover, ondrop) = match (item, props.on_move.clone()) {
+                        (PlaylistItem::Entry(entry), Some(on_move)) => {
+                            let entry_id = entry.id;
+                            let dragged_start = dragged.clone();
+                            let dragged_drop = dragged.clone();
+                            (
+                                true,
+                                Callback::from(move |_: DragEvent| dragged_start.set(Some(entry_id))),
+                                // Needed for the element to accept drops
+                                Callback::from(|e: DragEvent| e.prevent_default()),
+                                Callback::from(move |e: DragEvent| {
+                                    e.prevent_default();
+                                    if let Some(dragged_id) = *dragged_drop && dragged_id != entry_id {
+                                        on_move.emit((dragged_id, index as u32));
+                                    }
+                                    dragged_drop.set(None);
+                                }),
+                            )
+                        }
+                        _ => (false, Callback::noop(), Callback::noop(), Callback::noop()),
+                    };
+                    html! {
+                    <li id={ item.video().id.clone() } draggable={ draggable.to_string() } {ondragstart} {ondragover} {ondrop}>
                         <div>
                             <p>
-                                { "Title : "}{ v.title.clone() }{ v.id.clone() }
+                                { "Title : "}{ item.video().title.clone() }{ item.video().id.clone() }
                             </p>
-                            <img src={ v.thumbnail.clone() } width=600 height=400 />
+                            if let PlaylistItem::Entry(entry) = item {
+                                if entry.auto_added {
+                                    <p>{ "Added by the autoplay" }</p>
+                                } else {
+                                    <p>{ "Added by : " }{ entry.added_by.clone() }</p>
+                                }
+                            }
+                            <img src={ item.video().thumbnail.clone() } width=600 height=400 />
                             {
                                 props.callbacks.clone().iter().map(|c| html! {
-                                    <Button info={ v.clone() } index={ i } callback={ c.clone() }/>
+                                    <Button item={ item.clone() } callback={ c.clone() }/>
                                 }).collect::<Html>()
                             }
                             // <Button info={ v.clone() } callback={ props.callback.clone() } index={ i } />
                         </div>
                     </li>
+                    }
                 }).collect::<Html>()
             }
         </ul>
@@ -59,32 +147,59 @@ pub fn playlist(props: &PlaylistProp) -> Html {
 
 #[derive(Properties, PartialEq)]
 pub struct ButtonProp {
-    pub info: Video,
+    pub item: PlaylistItem,
     pub callback: PlaylistAction,
-    pub index: usize,
 }
 
 #[function_component(Button)]
 pub fn button(props: &ButtonProp) -> Html {
-    let info = props.info.clone();
-    let index = props.index;
-    let (callback, text) = match props.callback.clone() {
-        PlaylistAction::Add(cb) => (
+    let info = props.item.video().clone();
+    // Actions on the queue are only available for its entries
+    let entry_id = match &props.item {
+        PlaylistItem::Entry(entry) => Some(entry.id),
+        PlaylistItem::Video(_) => None,
+    };
+    let (callback, text) = match (props.callback.clone(), entry_id) {
+        (PlaylistAction::Add(cb), _) => (
             Callback::from(move |_| cb.clone().emit(info.clone())),
             "Add",
         ),
-        PlaylistAction::Remove(cb) => (
-            Callback::from(move |_| cb.clone().emit((index, info.id.clone()))),
-            "Remove",
-        ),
-        PlaylistAction::MoveUp(cb) => (
-            Callback::from(move |_| cb.clone().emit((index, info.id.clone()))),
-            "MoveUp"
+        (PlaylistAction::Remove(cb), Some(entry_id)) => {
+            (Callback::from(move |_| cb.clone().emit(entry_id)), "Remove")
+        }
+        (PlaylistAction::MoveUp(cb), Some(entry_id)) => {
+            (Callback::from(move |_| cb.clone().emit(entry_id)), "MoveUp")
+        }
+        (PlaylistAction::MoveDown(cb), Some(entry_id)) => (
+            Callback::from(move |_| cb.clone().emit(entry_id)),
+            "MoveDown",
         ),
-        PlaylistAction::MoveDown(cb) => (
-            Callback::from(move |_| cb.clone().emit((index, info.id.clone()))),
-            "MoveDown"
+        (PlaylistAction::SetRate(cb), Some(entry_id)) => {
+            let rate = match &props.item {
+                PlaylistItem::Entry(entry) => entry.rate,
+                PlaylistItem::Video(_) => None,
+            };
+            let onchange = Callback::from(move |e: Event| {
+                if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                    cb.emit((entry_id, select.value().parse().ok()));
+                }
+            });
+            return html! {
+                <select {onchange}>
+                    <option value="" selected={ rate.is_none() }>{ "Global speed" }</option>
+                    {
+                        ENTRY_RATES.iter().map(|r| html! {
+                            <option value={ r.to_string() } selected={ rate == Some(*r) }>{ format!("{r}x") }</option>
+                        }).collect::<Html>()
+                    }
+                </select>
+            };
+        }
+        (PlaylistAction::PlayNext(cb), Some(entry_id)) => (
+            Callback::from(move |_| cb.clone().emit(entry_id)),
+            "PlayNext",
         ),
+        _ => return html! {},
     };
     html! {<button onclick={ callback.clone() }>{ text }</button>}
 }
@@ -26,6 +26,41 @@ pub async fn search_videos(search: &str) -> Result<Vec<YtVideoPageInfo>, ErrorEx
     YtPageData::new(url.as_str()).await?.videos_search_info()
 }
 
+/// To get the videos YouTube recommends after a video :
+/// ```
+/// use my_youtube_extractor::related_videos;
+/// use my_youtube_extractor::youtube_info::YtVideoPageInfo;
+///
+/// // Async is just here to show it must be in an async block
+/// async {
+///     let v: Vec<YtVideoPageInfo> = related_videos("ytWz0qVvBZ0").await.unwrap();
+/// };
+/// ```
+/// See [`crate::youtube_info::YtVideoPageInfo`]
+pub async fn related_videos(id: &str) -> Result<Vec<YtVideoPageInfo>, ErrorExtractor> {
+    let url = "https://www.youtube.com/watch?v=".to_owned() + id;
+
+    YtPageData::new(url.as_str()).await?.related_videos_info()
+}
+
+/// To get the videos of a playlist (only its first 100 videos) :
+/// ```
+/// use my_youtube_extractor::playlist_videos;
+/// use my_youtube_extractor::youtube_info::YtVideoPageInfo;
+///
+/// // Async is just here to show it must be in an async block
+/// async {
+///     let playlist_id = "PL..."; // Playlist link is : https://www.youtube.com/playlist?list=PL...
+///     let v: Vec<YtVideoPageInfo> = playlist_videos(playlist_id).await.unwrap();
+/// };
+/// ```
+/// See [`crate::youtube_info::YtVideoPageInfo`]
+pub async fn playlist_videos(id: &str) -> Result<Vec<YtVideoPageInfo>, ErrorExtractor> {
+    let url = "https://www.youtube.com/playlist?list=".to_owned() + id;
+
+    YtPageData::new(url.as_str()).await?.playlist_videos_info()
+}
+
 /// To get the audio link of a video :
 /// ```
 /// use my_youtube_extractor::get_best_audio;
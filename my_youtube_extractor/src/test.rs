@@ -5,7 +5,14 @@ use super::*;
 
 #[tokio::test]
 async fn test_search() {
-    assert!(!search_videos("youtube rewind").await.is_empty());
+    assert!(!search_videos("youtube rewind").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_related_videos() {
+    let videos = related_videos("YbJOTdZBX1g").await.unwrap();
+    assert!(!videos.is_empty());
+    assert!(videos.iter().all(|v| v.id != "YbJOTdZBX1g"));
 }
 
 #[tokio::test]
@@ -15,6 +22,55 @@ async fn test_url() {
     assert_eq!(resp.status(), reqwest::StatusCode::from_u16(200).unwrap());
 }
 
+#[tokio::test]
+async fn test_url_expire() {
+    let audio = get_best_audio("YbJOTdZBX1g").await.unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert!(audio.expire().unwrap() > now);
+}
+
+#[test]
+fn test_expire_offline() {
+    let audio = |url: &str| YtAudioData {
+        url: url.to_owned(),
+        itag: 251,
+        sample_rate: 48000,
+        bitrate: 160000,
+        channels: 2,
+        ms_duration: None,
+        loudness_db: None,
+    };
+    let cases = [
+        (
+            "https://rr1.googlevideo.com/videoplayback?expire=1700000000&ei=abc&itag=251",
+            Some(1700000000),
+        ),
+        (
+            "https://rr1.googlevideo.com/videoplayback?ei=abc&itag=251&expire=1700000000",
+            Some(1700000000),
+        ),
+        (
+            "https://rr1.googlevideo.com/videoplayback?ei=abc&noexpire=1700000000",
+            None,
+        ),
+        (
+            "https://rr1.googlevideo.com/videoplayback?ei=abc&itag=251",
+            None,
+        ),
+        (
+            "https://rr1.googlevideo.com/videoplayback?expire=soon",
+            None,
+        ),
+        ("https://rr1.googlevideo.com/videoplayback", None),
+    ];
+    for (url, expected) in cases {
+        assert_eq!(audio(url).expire(), expected, "{url}");
+    }
+}
+
 #[tokio::test]
 async fn test_age_restriction() {
     let test_get = get_best_audio("QdabIfmcqSQ").await;
@@ -73,6 +73,35 @@ impl YtPageData {
             .collect())
     }
 
+    /// Videos recommended next to the video of a watch page
+    pub fn related_videos_info(&self) -> Result<Vec<YtVideoPageInfo>, ErrorExtractor> {
+        if !self.url.contains("https://www.youtube.com/watch") {
+            panic!("{} is not a video page url", self.url);
+        }
+
+        Ok(YtPageData::get_related_videos(&self.yt_initial_data)?
+            .iter()
+            // Some results are grouped in sections
+            .flat_map(|v| match v.pointer("/itemSectionRenderer/contents") {
+                Some(Value::Array(contents)) => contents.iter().collect(),
+                _ => vec![v],
+            })
+            .filter_map(YtPageData::get_compact_video_info)
+            .collect())
+    }
+
+    /// Videos of a playlist page, only the first 100 ones are in the page
+    pub fn playlist_videos_info(&self) -> Result<Vec<YtVideoPageInfo>, ErrorExtractor> {
+        if !self.url.contains("https://www.youtube.com/playlist") {
+            panic!("{} is not a playlist url", self.url);
+        }
+
+        Ok(YtPageData::get_playlist_videos(&self.yt_initial_data)?
+            .iter()
+            .filter_map(YtPageData::get_playlist_video_info)
+            .collect())
+    }
+
     fn get_video_info(v: &Value) -> Option<YtVideoPageInfo> {
         let video_info = match v.get("videoRenderer") {
             None => return None,
@@ -181,6 +210,110 @@ impl YtPageData {
         }
     }
 
+    fn get_compact_video_info(v: &Value) -> Option<YtVideoPageInfo> {
+        let video_info = v.get("compactVideoRenderer")?;
+        let id = video_info.get("videoId")?.as_str()?.to_string();
+        // Live videos have no length
+        let Some(duration) = video_info.pointer("/lengthText/simpleText") else {
+            log::debug!("Live video : https://www.youtube.com/watch?v={}", id);
+            return None;
+        };
+
+        Some(YtVideoPageInfo {
+            id,
+            short_recap: "".to_string(),
+            title: video_info
+                .pointer("/title/simpleText")?
+                .as_str()?
+                .to_string(),
+            thumbnail: video_info
+                .pointer("/thumbnail/thumbnails/0/url")?
+                .as_str()?
+                .to_string(),
+            author: YtAuthorInfo {
+                name: video_info
+                    .pointer("/longBylineText/runs/0/text")?
+                    .as_str()?
+                    .to_string(),
+                thumbnail: video_info
+                    .pointer("/channelThumbnail/thumbnails/0/url")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                tag: "".to_string(),
+            },
+            meta_description: "".to_string(),
+            duration: duration.as_str()?.to_string(),
+            n_views: video_info
+                .pointer("/viewCountText/simpleText")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            date: video_info
+                .pointer("/publishedTimeText/simpleText")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        })
+    }
+
+    fn get_playlist_video_info(v: &Value) -> Option<YtVideoPageInfo> {
+        let video_info = v.get("playlistVideoRenderer")?;
+        let id = video_info.get("videoId")?.as_str()?.to_string();
+        // Deleted and private videos have no length
+        let Some(duration) = video_info.pointer("/lengthText/simpleText") else {
+            log::debug!("Unavailable video : https://www.youtube.com/watch?v={}", id);
+            return None;
+        };
+
+        Some(YtVideoPageInfo {
+            id,
+            short_recap: "".to_string(),
+            title: video_info
+                .pointer("/title/runs/0/text")?
+                .as_str()?
+                .to_string(),
+            thumbnail: video_info
+                .pointer("/thumbnail/thumbnails/0/url")?
+                .as_str()?
+                .to_string(),
+            author: YtAuthorInfo {
+                name: video_info
+                    .pointer("/shortBylineText/runs/0/text")?
+                    .as_str()?
+                    .to_string(),
+                thumbnail: "".to_string(),
+                tag: "".to_string(),
+            },
+            meta_description: "".to_string(),
+            duration: duration.as_str()?.to_string(),
+            n_views: "".to_string(),
+            date: "".to_string(),
+        })
+    }
+
+    fn get_related_videos(yt_initial_data: &Value) -> Result<&Vec<Value>, ErrorExtractor> {
+        yt_initial_data
+            .pointer(
+                "/contents/twoColumnWatchNextResults/secondaryResults/secondaryResults/results",
+            )
+            .ok_or(ErrorExtractor::ErrorParsing(
+                "Missing 'secondaryResults'".to_string(),
+            ))?
+            .as_array()
+            .ok_or(ErrorExtractor::ErrorParsing("No an array".to_string()))
+    }
+
+    fn get_playlist_videos(yt_initial_data: &Value) -> Result<&Vec<Value>, ErrorExtractor> {
+        yt_initial_data
+            .pointer("/contents/twoColumnBrowseResultsRenderer/tabs/0/tabRenderer/content/sectionListRenderer/contents/0/itemSectionRenderer/contents/0/playlistVideoListRenderer/contents")
+            .ok_or(ErrorExtractor::ErrorParsing(
+                "Missing 'playlistVideoListRenderer'".to_string(),
+            ))?
+            .as_array()
+            .ok_or(ErrorExtractor::ErrorParsing("No an array".to_string()))
+    }
+
     fn get_search_videos(yt_initial_data: &Value) -> Result<&Vec<Value>, ErrorExtractor> {
         yt_initial_data
             .get("contents")
@@ -131,4 +131,13 @@ impl YtAudioData {
             loudness_db,
         })
     }
+
+    /// Unix timestamp in seconds after which `url` is rejected by YouTube
+    pub fn expire(&self) -> Option<u64> {
+        let (_, query) = self.url.split_once('?')?;
+        query
+            .split('&')
+            .find_map(|param| param.strip_prefix("expire="))
+            .and_then(|expire| expire.parse().ok())
+    }
 }
@@ -1,18 +1,358 @@
 use anyhow::Result;
 use bincode::{config, Decode, Encode};
 use entity::video::Model;
+use serde::{Deserialize, Serialize};
 
+/// Version of the [`NetData`] protocol, to increase every time existing variants are changed or reordered
+pub const PROTOCOL_VERSION: u32 = 6;
+/// Oldest version of the protocol the server still understands
+pub const MIN_PROTOCOL_VERSION: u32 = 6;
+/// Optional messages this build knows about, new variants are only sent to clients announcing their capability
+pub const CAPABILITIES: &[&str] = &[
+    capability::SNAPSHOT,
+    capability::ERRORS,
+    capability::NOW_PLAYING,
+    capability::MOVE_TO,
+    capability::TRANSITIONS,
+    capability::NORMALIZATION,
+    capability::PLAY_MODE,
+    capability::EQUALIZER,
+    capability::PLAYBACK_RATE,
+];
+
+pub mod capability {
+    pub const SNAPSHOT: &str = "snapshot";
+    pub const ERRORS: &str = "errors";
+    pub const NOW_PLAYING: &str = "now_playing";
+    pub const MOVE_TO: &str = "move_to";
+    pub const TRANSITIONS: &str = "transitions";
+    pub const NORMALIZATION: &str = "normalization";
+    pub const PLAY_MODE: &str = "play_mode";
+    pub const EQUALIZER: &str = "equalizer";
+    pub const PLAYBACK_RATE: &str = "playback_rate";
+}
+
+/// Id given by the server to an entry of the queue, the same video can be queued several times
+pub type EntryId = i32;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Encode, Decode)]
+pub struct QueueEntry {
+    pub id: EntryId,
+    pub video: Model,
+    pub added_by: String,
+    pub added_at: i64,    // Unix timestamp in seconds
+    pub auto_added: bool, // Queued by the autoplay, the musics queued by the users are played first
+    /// Speed of this music, the global playback rate when None
+    pub rate: Option<f64>,
+}
+
+/// How a music played by the jukebox ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayOutcome {
+    Finished,
+    Skipped, // Skipped, removed or replaced while playing
+    Failed,  // The stream broke while playing
+}
+
+impl PlayOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlayOutcome::Finished => "finished",
+            PlayOutcome::Skipped => "skipped",
+            PlayOutcome::Failed => "failed",
+        }
+    }
+
+    pub fn parse(outcome: &str) -> Option<PlayOutcome> {
+        match outcome {
+            "finished" => Some(PlayOutcome::Finished),
+            "skipped" => Some(PlayOutcome::Skipped),
+            "failed" => Some(PlayOutcome::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// Music played by the jukebox, returned by `/api/history`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: i32,
+    pub video: Model,
+    pub added_by: String,
+    pub auto_added: bool,
+    pub started_at: i64, // Unix timestamp in seconds
+    pub ended_at: i64,   // Unix timestamp in seconds
+    pub outcome: PlayOutcome,
+}
+
+/// Page of the history, the most recent musics first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    pub total: u64, // Number of musics matching the filters
+}
+
+/// Id chosen by a client to match the Ack or Error answering one of its requests
+pub type RequestId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum ErrorKind {
+    NotFound, // The entry is not in the queue anymore
+    InvalidRequest,
+    SearchFailed,
+    Unplayable,
+    Internal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum PlaybackState {
+    Stopped,
+    Paused,
+    Playing,
+    Buffering,
+}
+
+/// Fades of the music player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct Transitions {
+    pub crossfade_ms: u32, // Overlap between two musics, 0 for gapless playback
+    pub fade_ms: u32,      // Fade on pause, resume and skip
+}
+
+impl Transitions {
+    pub const MAX_CROSSFADE_MS: u32 = 12_000;
+    pub const MAX_FADE_MS: u32 = 5_000;
+}
+
+impl Default for Transitions {
+    fn default() -> Self {
+        Transitions {
+            crossfade_ms: 0,
+            fade_ms: 500,
+        }
+    }
+}
+
+/// Gain applied to every music so that they are played at the same loudness
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+pub struct Normalization {
+    pub enabled: bool,
+    pub target_lufs: f64,
+}
+
+impl Normalization {
+    pub const MIN_TARGET_LUFS: f64 = -30.0;
+    pub const MAX_TARGET_LUFS: f64 = -5.0;
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Normalization {
+            enabled: true,
+            target_lufs: -14.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Encode, Decode)]
+pub enum Repeat {
+    #[default]
+    Off, // Musics are removed from the queue once played
+    One, // The music playing starts again at its end
+    All, // Musics go back to the end of the queue once played
+}
+
+impl Repeat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Repeat::Off => "off",
+            Repeat::One => "one",
+            Repeat::All => "all",
+        }
+    }
+
+    pub fn parse(repeat: &str) -> Option<Repeat> {
+        match repeat {
+            "off" => Some(Repeat::Off),
+            "one" => Some(Repeat::One),
+            "all" => Some(Repeat::All),
+            _ => None,
+        }
+    }
+}
+
+/// Order in which the queue is played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Encode, Decode)]
+pub struct PlayMode {
+    pub repeat: Repeat,
+    pub shuffle: bool, // The musics are queued at a random position
+}
+
+/// Bounds of the playback rates, the musics are sped up or slowed down without changing their pitch
+pub const MIN_PLAYBACK_RATE: f64 = 0.5;
+pub const MAX_PLAYBACK_RATE: f64 = 2.0;
+
+/// Number of bands of the [`Equalizer`]
+pub const EQUALIZER_BANDS: usize = 10;
+
+/// Gains of the equalizer picked in a list, the gains of a preset can be changed band by band afterward
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Encode, Decode)]
+pub enum EqualizerPreset {
+    #[default]
+    Flat,
+    LessBass, // For bass-heavy speakers
+    MoreBass,
+    MoreTreble,
+    Vocal,
+    Custom, // Gains set band by band
+}
+
+impl EqualizerPreset {
+    /// Presets with their own gains, offered to the users
+    pub const ALL: [EqualizerPreset; 5] = [
+        EqualizerPreset::Flat,
+        EqualizerPreset::LessBass,
+        EqualizerPreset::MoreBass,
+        EqualizerPreset::MoreTreble,
+        EqualizerPreset::Vocal,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EqualizerPreset::Flat => "flat",
+            EqualizerPreset::LessBass => "less_bass",
+            EqualizerPreset::MoreBass => "more_bass",
+            EqualizerPreset::MoreTreble => "more_treble",
+            EqualizerPreset::Vocal => "vocal",
+            EqualizerPreset::Custom => "custom",
+        }
+    }
+
+    pub fn parse(preset: &str) -> Option<EqualizerPreset> {
+        match preset {
+            "flat" => Some(EqualizerPreset::Flat),
+            "less_bass" => Some(EqualizerPreset::LessBass),
+            "more_bass" => Some(EqualizerPreset::MoreBass),
+            "more_treble" => Some(EqualizerPreset::MoreTreble),
+            "vocal" => Some(EqualizerPreset::Vocal),
+            "custom" => Some(EqualizerPreset::Custom),
+            _ => None,
+        }
+    }
+
+    /// Gains of the bands in dB, None for the custom gains
+    pub fn gains_db(&self) -> Option<[f64; EQUALIZER_BANDS]> {
+        match self {
+            EqualizerPreset::Flat => Some([0.0; EQUALIZER_BANDS]),
+            EqualizerPreset::LessBass => {
+                Some([-8.0, -6.0, -4.0, -2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+            }
+            EqualizerPreset::MoreBass => Some([6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            EqualizerPreset::MoreTreble => Some([0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0]),
+            EqualizerPreset::Vocal => Some([-3.0, -2.0, -1.0, 0.0, 2.0, 4.0, 4.0, 2.0, 0.0, -1.0]),
+            EqualizerPreset::Custom => None,
+        }
+    }
+}
+
+/// Gains applied by the music player to each band of frequencies of the mixed musics
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+pub struct Equalizer {
+    pub preset: EqualizerPreset,
+    pub gains_db: [f64; EQUALIZER_BANDS],
+}
+
+impl Equalizer {
+    /// Center frequencies of the bands in Hz, the ones of the GStreamer `equalizer-10bands` element
+    pub const FREQUENCIES_HZ: [u32; EQUALIZER_BANDS] =
+        [29, 59, 119, 237, 474, 947, 1889, 3770, 7523, 15011];
+    pub const MIN_GAIN_DB: f64 = -24.0;
+    pub const MAX_GAIN_DB: f64 = 12.0;
+
+    /// Equalizer with the gains of `preset`, or flat gains for the custom one
+    pub fn preset(preset: EqualizerPreset) -> Self {
+        Equalizer {
+            preset,
+            gains_db: preset.gains_db().unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for Equalizer {
+    fn default() -> Self {
+        Equalizer::preset(EqualizerPreset::Flat)
+    }
+}
+
+/// Message from a client expecting an Ack or an Error, the server answers the ones changing the jukebox
+/// by broadcasting the matching [`NetData`] to every client
 #[derive(Debug, Encode, Decode, Clone)]
-pub enum NetData {
+pub enum ClientRequest {
     Search(String),
-    SearchResult(Vec<Model>),
-    Remove(usize, String), // Index and id of the video
-    Add(Model),
+    Remove(EntryId),
+    Add(Model), // The server answers with Added
     Play,
     Pause,
     Next,
     SetVolume(f64),
-    Move(usize, String, i32), // Index | Video id | Delta of the move
+    Move(EntryId, i32),   // Entry | Delta of the move
+    Resync,               // Ask the server for a Snapshot
+    Seek(u64),            // Position in the music playing in milliseconds
+    MoveTo(EntryId, u32), // Entry | New index in the queue
+    SetTransitions(Transitions),
+    SetNormalization(Normalization),
+    SetPlayMode(PlayMode),
+    /// The server replaces the gains of a preset by its own ones
+    SetEqualizer(Equalizer),
+    SetEqualizerBand(u8, f64), // Band | Gain in dB, the preset becomes custom
+    SetRate(f64),              // Playback rate of the musics without their own one
+    /// Entry | Its own playback rate, None for the global one
+    SetEntryRate(EntryId, Option<f64>),
+}
+
+#[derive(Debug, Encode, Decode, Clone)]
+pub enum NetData {
+    // Must stay the first variant so that any version of the client and server can decode it
+    Hello {
+        protocol_version: u32,
+        capabilities: Vec<String>,
+    },
+    SearchResult(Vec<Model>),
+    Remove(EntryId),
+    Next,
+    SetVolume(f64),
+    Move(EntryId, i32), // Entry | Delta of the move
+    Resync,             // Makes the connection of a client send it a Snapshot
+    Snapshot {
+        queue: Vec<QueueEntry>, // The first entry is the one playing
+        now_playing: Option<QueueEntry>,
+        position_ms: u64,
+        paused: bool,
+        volume: f64,
+    },
+    Added(QueueEntry),
+    Request(RequestId, ClientRequest),
+    Ack(RequestId),
+    Error {
+        request_id: Option<RequestId>, // None when the error does not come from a request
+        kind: ErrorKind,
+        message: String,
+    },
+    NowPlaying {
+        entry: Option<QueueEntry>,
+        position_ms: u64,
+        duration_ms: Option<u64>,
+        state: PlaybackState,
+    },
+    MoveTo(EntryId, u32), // Entry | New index in the queue
+    SetTransitions(Transitions),
+    SetNormalization(Normalization),
+    SetPlayMode(PlayMode),
+    SetEqualizer(Equalizer),
+    SetRate(f64), // Playback rate of the musics without their own one
+    /// Entry | Its own playback rate, None for the global one
+    SetEntryRate(EntryId, Option<f64>),
 }
 
 impl NetData {
@@ -23,4 +363,94 @@ impl NetData {
     pub fn decode_message(bytes: &[u8]) -> Result<NetData> {
         Ok(bincode::decode_from_slice(bytes, config::standard())?.0)
     }
+
+    /// Message sent by a client to start the session
+    pub fn hello() -> NetData {
+        NetData::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    /// Capability the other side must have announced to understand this message
+    pub fn required_capability(&self) -> Option<&'static str> {
+        match self {
+            NetData::Snapshot { .. } => Some(capability::SNAPSHOT),
+            NetData::Ack(_) | NetData::Error { .. } => Some(capability::ERRORS),
+            NetData::NowPlaying { .. } => Some(capability::NOW_PLAYING),
+            NetData::MoveTo(..) => Some(capability::MOVE_TO),
+            NetData::SetTransitions(_) => Some(capability::TRANSITIONS),
+            NetData::SetNormalization(_) => Some(capability::NORMALIZATION),
+            NetData::SetPlayMode(_) => Some(capability::PLAY_MODE),
+            NetData::SetEqualizer(_) => Some(capability::EQUALIZER),
+            NetData::SetRate(_) | NetData::SetEntryRate(..) => Some(capability::PLAYBACK_RATE),
+            _ => None,
+        }
+    }
+
+    /// Whether the message changes the queue of the clients, which drifts when it is not applied
+    pub fn changes_queue(&self) -> bool {
+        matches!(
+            self,
+            NetData::Remove(_)
+                | NetData::Added(_)
+                | NetData::Next
+                | NetData::Move(..)
+                | NetData::MoveTo(..)
+                | NetData::SetEntryRate(..)
+        )
+    }
+
+    pub fn is_supported_by(&self, capabilities: &[String]) -> bool {
+        self.required_capability()
+            .map_or(true, |required| capabilities.iter().any(|c| c == required))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_repeat() {
+        let cases = [
+            ("off", Some(Repeat::Off)),
+            ("one", Some(Repeat::One)),
+            ("all", Some(Repeat::All)),
+            ("All", None),
+            ("twice", None),
+            ("", None),
+        ];
+        for (repeat, expected) in cases {
+            assert_eq!(Repeat::parse(repeat), expected, "{repeat:?}");
+        }
+        for repeat in [Repeat::Off, Repeat::One, Repeat::All] {
+            assert_eq!(Repeat::parse(repeat.as_str()), Some(repeat));
+        }
+    }
+
+    #[test]
+    fn parse_equalizer_preset() {
+        let cases = [
+            ("flat", Some(EqualizerPreset::Flat)),
+            ("less_bass", Some(EqualizerPreset::LessBass)),
+            ("more_bass", Some(EqualizerPreset::MoreBass)),
+            ("more_treble", Some(EqualizerPreset::MoreTreble)),
+            ("vocal", Some(EqualizerPreset::Vocal)),
+            ("custom", Some(EqualizerPreset::Custom)),
+            ("more bass", None),
+            ("MoreBass", None),
+            ("rock", None),
+            ("", None),
+        ];
+        for (preset, expected) in cases {
+            assert_eq!(EqualizerPreset::parse(preset), expected, "{preset:?}");
+        }
+        for preset in EqualizerPreset::ALL
+            .into_iter()
+            .chain([EqualizerPreset::Custom])
+        {
+            assert_eq!(EqualizerPreset::parse(preset.as_str()), Some(preset));
+        }
+    }
 }